@@ -0,0 +1,247 @@
+use crate::output::{self, Compression};
+use crate::primitives::SessionIndex;
+use crate::subscan::DisputeInitiated;
+use crate::{DisputeInitiator, InclusionPlottingPoint};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CsvKind {
+    Inclusion,
+    Disputes,
+}
+
+/// Infers the kind of a previously saved csv file from its header.
+pub fn infer_kind(path: &Path) -> anyhow::Result<CsvKind> {
+    let mut rdr = csv::Reader::from_reader(output::open(path)?);
+    let headers = rdr.headers()?;
+    if headers.iter().any(|h| h == "blocks") {
+        Ok(CsvKind::Inclusion)
+    } else if headers.iter().any(|h| h == "account_id") {
+        Ok(CsvKind::Disputes)
+    } else {
+        anyhow::bail!(
+            "can't infer the kind of {}, please specify --kind",
+            path.display()
+        )
+    }
+}
+
+/// Reads a previously saved csv file, which may be compressed with `--compress`.
+pub fn read_csv<T: serde::de::DeserializeOwned>(path: &Path) -> anyhow::Result<Vec<T>> {
+    let mut rdr = csv::Reader::from_reader(output::open(path)?);
+    let mut records = Vec::new();
+    for record in rdr.deserialize() {
+        records.push(record?);
+    }
+    crate::report::add_processed(records.len());
+    Ok(records)
+}
+
+fn percentile(sorted: &[u32], p: usize) -> u32 {
+    let idx = (sorted.len() * p / 100).min(sorted.len() - 1);
+    sorted[idx]
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct InclusionStats {
+    pub points: usize,
+    pub min: u32,
+    pub max: u32,
+    pub mean: f64,
+    pub std_dev: f64,
+    pub p50: u32,
+    pub p90: u32,
+    pub p99: u32,
+}
+
+impl InclusionStats {
+    pub fn compute(data: &[InclusionPlottingPoint]) -> Option<Self> {
+        if data.is_empty() {
+            return None;
+        }
+        let mut blocks: Vec<u32> = data.iter().map(|p| p.blocks).collect();
+        blocks.sort();
+
+        let n = blocks.len() as f64;
+        let mean = blocks.iter().map(|b| *b as f64).sum::<f64>() / n;
+        let variance = blocks
+            .iter()
+            .map(|b| (*b as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+
+        Some(Self {
+            points: blocks.len(),
+            min: blocks[0],
+            max: blocks[blocks.len() - 1],
+            mean,
+            std_dev: variance.sqrt(),
+            p50: percentile(&blocks, 50),
+            p90: percentile(&blocks, 90),
+            p99: percentile(&blocks, 99),
+        })
+    }
+}
+
+/// Prints the statistics of backing or inclusion times.
+///
+/// Points further than 3 standard deviations from the mean are reported as anomalies.
+pub fn summarize_inclusion(name: &str, data: &[InclusionPlottingPoint]) {
+    let Some(stats) = InclusionStats::compute(data) else {
+        return;
+    };
+    let InclusionStats {
+        points,
+        min,
+        max,
+        mean,
+        std_dev,
+        p50,
+        p90,
+        p99,
+    } = stats;
+
+    println!(
+        "{name}: {points} points, min={min}, max={max}, mean={mean:.2}, p50={p50}, p90={p90}, p99={p99}"
+    );
+
+    let threshold = mean + 3.0 * std_dev;
+    for p in data.iter().filter(|p| p.blocks as f64 > threshold) {
+        println!(
+            "{name}: anomaly at block {}: {} blocks",
+            p.block_num, p.blocks
+        );
+    }
+}
+
+/// Prints how the statistics changed from the scan up to `before.0` to the one up to `after.0`.
+pub fn print_stats_diff(name: &str, before: (u32, &InclusionStats), after: (u32, &InclusionStats)) {
+    let (before_block, before) = before;
+    let (after_block, after) = after;
+    println!("{name}: up to block {before_block} -> up to block {after_block}");
+    println!(
+        "  mean: {:.2} -> {:.2} ({:+.2})",
+        before.mean,
+        after.mean,
+        after.mean - before.mean
+    );
+    for (what, before, after) in [
+        ("p50", before.p50, after.p50),
+        ("p90", before.p90, after.p90),
+        ("p99", before.p99, after.p99),
+        ("max", before.max, after.max),
+    ] {
+        println!(
+            "  {what}: {before} -> {after} ({:+})",
+            after as i64 - before as i64
+        );
+    }
+}
+
+/// Prints the number of initiated disputes per session and per validator.
+pub fn summarize_disputes(data: &[DisputeInitiator]) {
+    let mut per_session: BTreeMap<SessionIndex, usize> = BTreeMap::new();
+    let mut per_account: BTreeMap<&str, usize> = BTreeMap::new();
+    for i in data {
+        *per_session.entry(i.session_index).or_default() += 1;
+        *per_account.entry(i.account_id.as_str()).or_default() += 1;
+    }
+
+    println!(
+        "{} disputes initiated by {} validators in {} sessions",
+        data.len(),
+        per_account.len(),
+        per_session.len(),
+    );
+    for (session, count) in per_session {
+        println!("session {session}: {count}");
+    }
+
+    let mut per_account: Vec<_> = per_account.into_iter().collect();
+    per_account.sort_by(|a, b| b.1.cmp(&a.1));
+    for (account, count) in per_account {
+        println!("{account}: {count}");
+    }
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct SessionSummary {
+    pub session_index: SessionIndex,
+    pub disputes: usize,
+    pub invalid_voters: usize,
+    pub first_block: u32,
+    pub last_block: u32,
+}
+
+/// Groups the initiated disputes by session.
+pub fn summarize_sessions(data: &[DisputeInitiated]) -> Vec<SessionSummary> {
+    let mut sessions: BTreeMap<SessionIndex, Vec<&DisputeInitiated>> = BTreeMap::new();
+    for i in data {
+        sessions.entry(i.session_index).or_default().push(i);
+    }
+
+    sessions
+        .into_iter()
+        .map(|(session_index, initiated)| {
+            // without the candidate hash, tell the disputes apart by their block
+            let disputes: BTreeSet<_> = initiated
+                .iter()
+                .map(|i| i.candidate_hash.ok_or(i.block_num))
+                .collect();
+            let voters: BTreeSet<_> = initiated.iter().map(|i| i.validator_index).collect();
+            let blocks = initiated.iter().map(|i| i.block_num);
+            SessionSummary {
+                session_index,
+                disputes: disputes.len(),
+                invalid_voters: voters.len(),
+                first_block: blocks.clone().min().unwrap_or_default(),
+                last_block: blocks.max().unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+pub fn write_json<T: serde::Serialize>(
+    path: &Path,
+    data: &[T],
+    compress: Compression,
+    pretty: bool,
+) -> anyhow::Result<()> {
+    let (file, path) = output::create(&path.to_string_lossy(), compress)?;
+    output::write_json(file, data, pretty)?;
+    eprintln!("Saved the data to {path}");
+    crate::report::add_output(path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analyze_compressed_outputs() {
+        let dir = tempfile::tempdir().unwrap();
+        let points =
+            [(10, 1), (12, 2), (15, 3)].map(|(block_num, blocks)| InclusionPlottingPoint {
+                block_num,
+                blocks,
+                params: None,
+                author: None,
+                session_index: None,
+            });
+        for compress in [Compression::None, Compression::Gzip, Compression::Zstd] {
+            let stem = dir.path().join(format!("inclusion-{compress:?}.csv"));
+            let (path, _) =
+                output::write_csv(&stem.to_string_lossy(), compress, false, points.clone())
+                    .unwrap();
+
+            let path = Path::new(&path);
+            assert_eq!(infer_kind(path).unwrap(), CsvKind::Inclusion);
+            let data: Vec<InclusionPlottingPoint> = read_csv(path).unwrap();
+            let stats = InclusionStats::compute(&data).unwrap();
+            assert_eq!((stats.points, stats.min, stats.max), (3, 1, 3));
+        }
+    }
+}
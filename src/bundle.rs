@@ -0,0 +1,92 @@
+use crate::primitives::{CandidateReceipt, H256};
+use anyhow::Context as _;
+use std::path::Path;
+
+pub const POV_FILE: &str = "pov.scale";
+pub const RECEIPT_FILE: &str = "receipt.scale";
+pub const PVF_FILE: &str = "pvf.scale";
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+#[derive(serde::Serialize)]
+pub struct Manifest {
+    pub network: String,
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub candidate_hash: H256,
+    pub para_id: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub para_name: Option<String>,
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub relay_parent: H256,
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub validation_code_hash: H256,
+    pub pov_size: usize,
+    pub receipt_size: usize,
+    pub pvf_size: usize,
+}
+
+fn read_cached(path: &Path) -> anyhow::Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| {
+        format!(
+            "{} is not cached, run validate-candidate first",
+            path.display()
+        )
+    })
+}
+
+/// Packs the cached PoV, receipt and validation code of a candidate into a `.tar.gz`
+/// together with a manifest.
+///
+/// The files are SCALE encoded as in the cache, so they can be passed to
+/// `validate-candidate --pov-file --receipt-file --pvf-file`.
+pub fn export(
+    cache: &Path,
+    network: &str,
+    candidate_hash: &H256,
+    out: &Path,
+    with_names: bool,
+) -> anyhow::Result<()> {
+    let candidate = format!("{candidate_hash:?}");
+    let povs_path = cache.join("povs");
+    let pov = read_cached(&povs_path.join(&candidate))?;
+    let receipt_bytes = read_cached(&povs_path.join("receipts").join(&candidate))?;
+
+    let receipt: CandidateReceipt<H256> =
+        parity_scale_codec::decode_from_bytes(receipt_bytes.clone().into())?;
+    let para_id = receipt.descriptor.para_id.0;
+    let code_hash = receipt.descriptor.validation_code_hash.0;
+    let pvf = read_cached(&cache.join("pvfs").join(format!("{code_hash:?}")))?;
+
+    let manifest = Manifest {
+        network: network.to_owned(),
+        candidate_hash: *candidate_hash,
+        para_id,
+        para_name: crate::names::manifest_name(network, para_id, with_names),
+        relay_parent: receipt.descriptor.relay_parent,
+        validation_code_hash: code_hash,
+        pov_size: pov.len(),
+        receipt_size: receipt_bytes.len(),
+        pvf_size: pvf.len(),
+    };
+    let manifest = serde_json::to_vec_pretty(&manifest)?;
+
+    let file = std::fs::File::create(out)?;
+    let encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    for (name, data) in [
+        (MANIFEST_FILE, &manifest),
+        (POV_FILE, &pov),
+        (RECEIPT_FILE, &receipt_bytes),
+        (PVF_FILE, &pvf),
+    ] {
+        let mut header = tar::Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        builder.append_data(&mut header, name, data.as_slice())?;
+    }
+    builder.into_inner()?.finish()?;
+
+    eprintln!("Saved the bundle to {}", out.display());
+    crate::report::add_output(out);
+    Ok(())
+}
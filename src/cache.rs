@@ -0,0 +1,167 @@
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+static REFRESH: AtomicBool = AtomicBool::new(false);
+
+/// The cache entries written by this run, the only ones used with `--refresh-cache`.
+static REFRESHED: Mutex<BTreeSet<PathBuf>> = Mutex::new(BTreeSet::new());
+
+/// Makes the entries cached by the previous runs stale, so that they are fetched again
+/// and overwritten.
+pub fn set_refresh(enabled: bool) {
+    REFRESH.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether the cache entry at `path` exists and isn't stale.
+pub fn is_cached(path: &Path) -> bool {
+    path.exists()
+        && (!REFRESH.load(Ordering::Relaxed) || REFRESHED.lock().expect("poisoned").contains(path))
+}
+
+static LOCKS: Mutex<BTreeMap<PathBuf, Arc<tokio::sync::Mutex<()>>>> = Mutex::new(BTreeMap::new());
+
+/// Locks the cache entry at `path`, so that concurrent fetches of the same entry
+/// don't race on writing it. The second fetch then finds the entry cached.
+pub async fn lock(path: &Path) -> Lock {
+    let lock = LOCKS
+        .lock()
+        .expect("poisoned")
+        .entry(path.to_path_buf())
+        .or_default()
+        .clone();
+    Lock {
+        path: path.to_path_buf(),
+        guard: Some(lock.lock_owned().await),
+    }
+}
+
+/// The lock of a cache entry, removed from `LOCKS` once nobody holds or waits for it.
+pub struct Lock {
+    path: PathBuf,
+    guard: Option<tokio::sync::OwnedMutexGuard<()>>,
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        self.guard.take();
+        // the waiters clone the lock while holding `LOCKS`, so none can appear meanwhile
+        let mut locks = LOCKS.lock().expect("poisoned");
+        if locks
+            .get(&self.path)
+            .is_some_and(|lock| Arc::strong_count(lock) == 1)
+        {
+            locks.remove(&self.path);
+        }
+    }
+}
+
+/// Writes the cache entry to a temporary file, then renames it into place,
+/// so that readers never see a partially written entry.
+pub fn write_atomic(path: &Path, data: &[u8]) -> std::io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(format!(".tmp-{}", std::process::id()));
+    let tmp = PathBuf::from(tmp);
+    std::fs::write(&tmp, data)?;
+    std::fs::rename(&tmp, path).map_err(|e| {
+        let _ = std::fs::remove_file(&tmp);
+        e
+    })?;
+    REFRESHED
+        .lock()
+        .expect("poisoned")
+        .insert(path.to_path_buf());
+    Ok(())
+}
+
+/// A bounded in-memory cache, evicting the least recently used entries.
+///
+/// Lookups are linear, it's meant for a handful of large entries.
+pub struct Lru<K, V> {
+    capacity: usize,
+    /// The most recently used entry first.
+    entries: VecDeque<(K, V)>,
+}
+
+impl<K: PartialEq, V: Clone> Default for Lru<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: PartialEq, V: Clone> Lru<K, V> {
+    /// Creates a disabled cache, see [`Lru::set_capacity`].
+    pub const fn new() -> Self {
+        Self {
+            capacity: 0,
+            entries: VecDeque::new(),
+        }
+    }
+
+    /// Limits the number of entries, zero disables the cache.
+    pub fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        self.entries.truncate(capacity);
+    }
+
+    pub fn get(&mut self, key: &K) -> Option<V> {
+        let position = self.entries.iter().position(|(k, _)| k == key)?;
+        let entry = self.entries.remove(position)?;
+        let value = entry.1.clone();
+        self.entries.push_front(entry);
+        Some(value)
+    }
+
+    pub fn insert(&mut self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        self.entries.retain(|(k, _)| *k != key);
+        self.entries.push_front((key, value));
+        self.entries.truncate(self.capacity);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use parity_scale_codec::Encode as _;
+    use std::time::Duration;
+
+    /// Fetches the entry like `get_or_fetch_candidate`, returns whether it was written.
+    async fn get_or_fetch(path: PathBuf, payload: Vec<u8>) -> std::io::Result<bool> {
+        let _guard = lock(&path).await;
+        if path.exists() {
+            return Ok(false);
+        }
+        // the fetch, giving the other task a chance to race
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        write_atomic(&path, &payload)?;
+        Ok(true)
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_fetches_of_the_same_candidate() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("0xc0ffee");
+        let candidate = b"the candidate".to_vec();
+        let payload = candidate.encode();
+
+        let first = tokio::spawn(get_or_fetch(path.clone(), payload.clone()));
+        let second = tokio::spawn(get_or_fetch(path.clone(), payload.clone()));
+        let written = [first.await.unwrap(), second.await.unwrap()];
+
+        let written: Vec<bool> = written.into_iter().map(Result::unwrap).collect();
+        assert_eq!(written.iter().filter(|w| **w).count(), 1);
+        let files: Vec<PathBuf> = std::fs::read_dir(dir.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        assert_eq!(files, [path.clone()], "no temporary files are left");
+        let bytes = std::fs::read(&path).unwrap();
+        let decoded: Vec<u8> = parity_scale_codec::decode_from_bytes(bytes.into()).unwrap();
+        assert_eq!(decoded, candidate);
+        assert!(!LOCKS.lock().unwrap().contains_key(&path));
+    }
+}
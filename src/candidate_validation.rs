@@ -1,9 +1,13 @@
-use crate::primitives::{AvailableData, BlockData, ValidationCode, ValidationParams};
+use crate::diagnostics::Diagnostics;
+use crate::error::{ErrorContext, ErrorKind};
+use crate::primitives::{
+    AvailableData, BlockData, ValidationCode, ValidationParams, ValidationResult, H256,
+};
+use anyhow::Context as _;
 use futures::channel::oneshot;
-use futures::future::FutureExt;
 use parity_scale_codec::Encode as _;
-use polkadot_node_core_pvf::{Config, PrepareJobKind, PvfPrepData};
-use std::path::PathBuf;
+use polkadot_node_core_pvf::{Config, PrepareJobKind, PvfPrepData, ValidationHost};
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 // TODO: proper errors
@@ -11,14 +15,91 @@ fn other_io_error(s: String) -> std::io::Error {
     std::io::Error::new(std::io::ErrorKind::Other, s)
 }
 
-pub async fn validate_candidate(
-    pvfs_path: PathBuf,
-    pov: AvailableData,
-    pvf: ValidationCode,
-    node_version: String,
-) -> anyhow::Result<()> {
+/// Compression schemes we can recognize by their magic bytes, but can't decompress.
+///
+/// The magic bytes can also be the start of an uncompressed PoV, so they are only used to
+/// explain a failure, never to reject a PoV up front.
+const UNSUPPORTED_COMPRESSION: &[(&str, &[u8])] = &[
+    (
+        "zstd without the substrate prefix",
+        &[0x28, 0xb5, 0x2f, 0xfd],
+    ),
+    ("gzip", &[0x1f, 0x8b]),
+    ("bzip2", b"BZh"),
+    ("lz4", &[0x04, 0x22, 0x4d, 0x18]),
+    ("xz", &[0xfd, 0x37, 0x7a, 0x58, 0x5a, 0x00]),
+];
+
+/// Returns a hint if the PoV block data starts with the magic of an unsupported compression.
+fn compression_hint(data: &[u8]) -> Option<String> {
+    let magic = &data[..data.len().min(8)];
+    UNSUPPORTED_COMPRESSION
+        .iter()
+        .find(|(_, prefix)| data.starts_with(prefix))
+        .map(|(scheme, _)| {
+            format!("the PoV might use an unsupported compression: {scheme} (magic {magic:02x?})")
+        })
+}
+
+/// Decompresses the PoV block data, which is either prefixed zstd or uncompressed.
+fn decompress_pov(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let magic = &data[..data.len().min(8)];
+    let raw = sp_maybe_compressed_blob::decompress(data, 20 * 1024 * 1024)
+        .with_context(|| format!("failed to decompress PoV (magic {magic:02x?})"))?;
+    Ok(raw.to_vec())
+}
+
+/// How to run the validation.
+#[derive(Clone, Copy)]
+pub struct ValidationOptions {
+    /// Print the persisted validation data of the PoV.
+    pub show_validation_data: bool,
+    /// Execute the candidate this many times, checking that the results are the same.
+    pub runs: u32,
+    /// Report the complete errors of the PVF host, instead of a one line summary.
+    pub verbose_errors: bool,
+    /// Use the PoV block data as is, without trying to decompress it.
+    pub assume_uncompressed: bool,
+}
+
+/// Decompresses the validation code, which is either prefixed zstd or uncompressed.
+pub fn decompress_pvf(pvf: &ValidationCode) -> anyhow::Result<Vec<u8>> {
+    Ok(sp_maybe_compressed_blob::decompress(&pvf.0, 12 * 1024 * 1024)?.to_vec())
+}
+
+/// The PVF validation host shared by the validations of a run, running in the background.
+pub struct PvfHost {
+    host: ValidationHost,
+    worker: tokio::task::JoinHandle<()>,
+}
+
+impl PvfHost {
+    pub fn handle(&self) -> &ValidationHost {
+        &self.host
+    }
+
+    /// Stops the host, which kills its worker processes and removes their
+    /// sockets and temporary folders. The compiled artifacts are kept.
+    ///
+    /// The host stops once all the handles are dropped, so the validations must be done.
+    pub async fn shutdown(self) {
+        let Self { host, worker } = self;
+        drop(host);
+        if tokio::time::timeout(Duration::from_secs(5), worker)
+            .await
+            .is_err()
+        {
+            eprintln!("Warning: the PVF host didn't shut down within 5s");
+        }
+    }
+}
+
+/// Starts the PVF validation host shared by the validations of a run.
+///
+/// The host keeps running in the background until [`PvfHost::shutdown`].
+pub async fn start_host(pvfs_path: PathBuf, node_version: String) -> anyhow::Result<PvfHost> {
     let program_path = std::env::current_exe()?;
-    let (mut validation_host, worker) = polkadot_node_core_pvf::start(
+    let (host, worker) = polkadot_node_core_pvf::start(
         Config::new(
             pvfs_path,
             Some(node_version),
@@ -28,15 +109,107 @@ pub async fn validate_candidate(
         Default::default(),
     )
     .await?;
+    let worker = tokio::spawn(worker);
+    Ok(PvfHost { host, worker })
+}
+
+/// Prechecks the validation code, which compiles it into an artifact of the host,
+/// so that the executions don't have to compile it again.
+pub async fn prepare_pvf(
+    validation_host: &mut ValidationHost,
+    pvf: &ValidationCode,
+    verbose_errors: bool,
+    log: &Diagnostics,
+) -> anyhow::Result<PvfPrepData> {
+    let raw_validation_code = decompress_pvf(pvf)?;
+
+    log.line("Pvf prechecking...");
+    let pvf = PvfPrepData::from_code(
+        raw_validation_code,
+        Default::default(),
+        Duration::from_secs(60),
+        PrepareJobKind::Prechecking,
+    );
+    let (tx, rx) = oneshot::channel();
+
+    let now = Instant::now();
+    let spinner = crate::progress::spinner("Pvf preparation");
+    validation_host
+        .precheck_pvf(pvf.clone(), tx)
+        .await
+        .map_err(other_io_error)?;
+    let result = rx.await;
+    spinner.finish_and_clear();
+    result?.map_err(|e| worker_error(&e, verbose_errors))?;
+    let elapsed = now.elapsed().as_millis();
 
-    let raw_block_data =
-        sp_maybe_compressed_blob::decompress(&pov.pov.block_data.0, 20 * 1024 * 1024)?.to_vec();
+    log.line(format!("Pvf preparation took {elapsed}ms"));
+    log.record(|f| {
+        f.preparation_ms.get_or_insert(elapsed as u64);
+    });
+    Ok(pvf)
+}
 
-    println!("PoV size: {}kb", raw_block_data.len() / 1024);
+// spread over multiple lines when verbose, so that e.g. a panic message of a worker is readable
+fn worker_error(e: &dyn std::fmt::Debug, verbose: bool) -> std::io::Error {
+    if verbose {
+        other_io_error(format!("{e:#?}"))
+    } else {
+        other_io_error(format!("{e:?}"))
+    }
+}
+
+pub async fn validate_candidate(
+    mut validation_host: ValidationHost,
+    pov: AvailableData,
+    pvf: ValidationCode,
+    options: &ValidationOptions,
+    log: &Diagnostics,
+) -> anyhow::Result<Option<ValidationResult>> {
+    let ValidationOptions {
+        show_validation_data,
+        runs,
+        verbose_errors,
+        assume_uncompressed,
+    } = *options;
+
+    // only consulted if the parachain fails to decode the block data as it is
+    let hint = compression_hint(&pov.pov.block_data.0);
+    let raw_block_data = if assume_uncompressed {
+        pov.pov.block_data.0
+    } else {
+        decompress_pov(&pov.pov.block_data.0)?
+    };
+
+    log.line(format!("PoV size: {}kb", raw_block_data.len() / 1024));
+    log.record(|f| {
+        f.pov_size.get_or_insert(raw_block_data.len());
+    });
     let block_data = BlockData(raw_block_data);
 
     let persisted_validation_data = pov.validation_data;
 
+    if show_validation_data {
+        let parent_head = &persisted_validation_data.parent_head;
+        log.line(format!(
+            "Parent head: {:?} ({} bytes)",
+            parent_head.hash(),
+            parent_head.0.len()
+        ));
+        log.line(format!(
+            "Relay parent number: {}",
+            persisted_validation_data.relay_parent_number
+        ));
+        log.line(format!(
+            "Relay parent storage root: {:?}",
+            persisted_validation_data.relay_parent_storage_root
+        ));
+        log.line(format!(
+            "Max PoV size: {}kb",
+            persisted_validation_data.max_pov_size / 1024
+        ));
+    }
+
     let params = ValidationParams {
         parent_head: persisted_validation_data.parent_head.clone(),
         block_data,
@@ -44,38 +217,17 @@ pub async fn validate_candidate(
         relay_parent_storage_root: persisted_validation_data.relay_parent_storage_root,
     };
 
-    let raw_validation_code =
-        sp_maybe_compressed_blob::decompress(&pvf.0, 12 * 1024 * 1024)?.to_vec();
-
-    let task = async move {
-        // precheck PVF
-        println!("Pvf prechecking...");
-        let pvf = PvfPrepData::from_code(
-            raw_validation_code,
-            Default::default(),
-            Duration::from_secs(60),
-            PrepareJobKind::Prechecking,
-        );
-        {
-            let (tx, rx) = oneshot::channel();
-
-            let now = Instant::now();
-            validation_host
-                .precheck_pvf(pvf.clone(), tx)
-                .await
-                .map_err(other_io_error)?;
-            rx.await?.map_err(|e| other_io_error(format!("{e:?}")))?;
-            let elapsed = now.elapsed().as_millis();
-
-            println!("Pvf preparation took {elapsed}ms");
-        }
+    let pvf = prepare_pvf(&mut validation_host, &pvf, verbose_errors, log).await?;
 
-        println!("Pvf execution...");
+    // execution must be deterministic, so every run should produce the same result
+    let mut first_result: Option<ValidationResult> = None;
+    for run in 1..=runs {
+        log.line("Pvf execution...");
         let (tx, rx) = oneshot::channel();
         let now = Instant::now();
         validation_host
             .execute_pvf(
-                pvf,
+                pvf.clone(),
                 Duration::from_secs(12),
                 params.encode(),
                 polkadot_node_core_pvf::Priority::Normal,
@@ -84,19 +236,219 @@ pub async fn validate_candidate(
             .await
             .map_err(other_io_error)?;
 
-        rx.await?.map_err(|e| other_io_error(format!("{e:?}")))?;
+        let result = rx
+            .await?
+            .map_err(|e| {
+                let e = anyhow::Error::new(worker_error(&e, verbose_errors));
+                match &hint {
+                    Some(hint) => e.context(hint.clone()),
+                    None => e,
+                }
+            })
+            .context(ErrorContext::new(ErrorKind::Validation))?;
         let elapsed = now.elapsed().as_millis();
 
-        println!("Execution took {elapsed}ms");
+        log.line(format!("Execution took {elapsed}ms"));
+        log.record(|f| {
+            f.execution_ms.get_or_insert(elapsed as u64);
+        });
+
+        match &first_result {
+            None => first_result = Some(result),
+            Some(first) if first.encode() != result.encode() => {
+                return Err(anyhow::anyhow!(
+                    "non-deterministic execution: run {run} of {runs} differs from the first one"
+                )
+                .context(ErrorContext::new(ErrorKind::Validation)));
+            }
+            Some(_) => {}
+        }
+    }
+    if runs > 1 {
+        log.line(format!("All {runs} executions produced the same result"));
+    }
+
+    Ok(first_result)
+}
+
+/// Erasure codes the available data into a chunk per validator
+/// and returns the merkle root of the chunks.
+pub fn erasure_root(n_validators: usize, data: &AvailableData) -> anyhow::Result<H256> {
+    let chunks = polkadot_erasure_coding::obtain_chunks_v1(n_validators, data)
+        .map_err(|e| anyhow::anyhow!("failed to erasure code the available data: {e:?}"))?;
+    let root = polkadot_erasure_coding::branches(&chunks).root();
+    Ok(H256(root.0))
+}
+
+/// Hashes the validation result the way the `CandidateCommitments` of the receipt are hashed.
+///
+/// The commitments consist of the same fields in the same order, so their SCALE
+/// encoding is the concatenation of the fields of the result.
+pub fn commitments_hash(result: &ValidationResult) -> H256 {
+    let encoded = (
+        &result.upward_messages,
+        &result.horizontal_messages,
+        &result.new_validation_code,
+        &result.head_data,
+        result.processed_downward_messages,
+        result.hrmp_watermark,
+    )
+        .encode();
+    crate::subxt::hash(&encoded)
+}
+
+/// Bytes serialized with the `--output-encoding`.
+struct Bytes<'a>(&'a [u8]);
+
+impl serde::Serialize for Bytes<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        crate::output::serialize_bytes(&self.0, serializer)
+    }
+}
 
-        Result::<(), anyhow::Error>::Ok(())
+#[derive(serde::Serialize)]
+struct HrmpMessage<'a> {
+    recipient: u32,
+    data: Bytes<'a>,
+}
+
+/// The JSON form of the [`ValidationResult`].
+#[derive(serde::Serialize)]
+struct SavedResult<'a> {
+    head_data: Bytes<'a>,
+    new_validation_code: Option<Bytes<'a>>,
+    upward_messages: Vec<Bytes<'a>>,
+    horizontal_messages: Vec<HrmpMessage<'a>>,
+    processed_downward_messages: u32,
+    hrmp_watermark: u32,
+}
+
+/// Writes the complete validation result to `path`, as JSON if the extension is `json`
+/// and SCALE encoded otherwise.
+pub fn save_result(path: &Path, result: &ValidationResult) -> anyhow::Result<()> {
+    let is_json = path.extension().map_or(false, |ext| ext == "json");
+    let bytes = if is_json {
+        let saved = SavedResult {
+            head_data: Bytes(&result.head_data.0),
+            new_validation_code: result.new_validation_code.as_ref().map(|c| Bytes(&c.0)),
+            upward_messages: result
+                .upward_messages
+                .iter()
+                .map(|m| Bytes(m.as_slice()))
+                .collect(),
+            horizontal_messages: result
+                .horizontal_messages
+                .iter()
+                .map(|m| HrmpMessage {
+                    recipient: m.recipient.into(),
+                    data: Bytes(&m.data),
+                })
+                .collect(),
+            processed_downward_messages: result.processed_downward_messages,
+            hrmp_watermark: result.hrmp_watermark,
+        };
+        serde_json::to_vec_pretty(&saved)?
+    } else {
+        result.encode()
     };
+    std::fs::write(path, bytes).with_context(|| format!("failed to write {}", path.display()))
+}
 
-    futures::pin_mut!(task);
-    futures::pin_mut!(worker);
+/// A row of the summary table of a batch validation.
+#[derive(Clone, Debug, serde::Serialize)]
+pub struct ValidationSummary {
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub candidate_hash: H256,
+    pub para_id: Option<u32>,
+    pub pov_size: Option<usize>,
+    pub preparation_ms: Option<u64>,
+    pub execution_ms: Option<u64>,
+    pub passed: bool,
+    pub error: Option<String>,
+}
+
+/// Prints the summaries as a table, one candidate per line.
+pub fn print_summaries(summaries: &[ValidationSummary]) {
+    fn or_dash(value: Option<impl std::fmt::Display>) -> String {
+        value.map_or_else(|| "-".to_owned(), |v| v.to_string())
+    }
 
-    futures::select! {
-        result = task.fuse() => Ok(result?),
-        _ = worker.fuse() => Ok(()),
+    println!(
+        "{:<66} {:>7} {:>8} {:>8} {:>8} {:<6} error",
+        "candidate", "para_id", "pov_kb", "prep_ms", "exec_ms", "result"
+    );
+    for s in summaries {
+        println!(
+            "{:<66} {:>7} {:>8} {:>8} {:>8} {:<6} {}",
+            format!("{:?}", s.candidate_hash),
+            or_dash(s.para_id),
+            or_dash(s.pov_size.map(|size| size / 1024)),
+            or_dash(s.preparation_ms),
+            or_dash(s.execution_ms),
+            if s.passed { "ok" } else { "FAILED" },
+            s.error.as_deref().unwrap_or(""),
+        );
+    }
+}
+
+/// What the candidate did, as far as the validation result tells.
+#[derive(Clone, Debug, serde::Serialize, schemars::JsonSchema)]
+pub struct ValidationOutcome {
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub candidate_hash: H256,
+    #[schemars(with = "String")]
+    #[serde(serialize_with = "crate::output::serialize_bytes")]
+    pub head_data_hash: H256,
+    pub head_data_size: usize,
+    pub new_validation_code_size: Option<usize>,
+    pub upward_messages: usize,
+    pub horizontal_messages: usize,
+    pub processed_downward_messages: u32,
+    pub hrmp_watermark: u32,
+    /// Whether the result matches the commitments of the receipt.
+    pub commitments_match: bool,
+}
+
+impl ValidationOutcome {
+    pub fn new(
+        candidate_hash: H256,
+        result: &ValidationResult,
+        expected_commitments: H256,
+    ) -> Self {
+        Self {
+            candidate_hash,
+            head_data_hash: result.head_data.hash(),
+            head_data_size: result.head_data.0.len(),
+            new_validation_code_size: result.new_validation_code.as_ref().map(|c| c.0.len()),
+            upward_messages: result.upward_messages.len(),
+            horizontal_messages: result.horizontal_messages.len(),
+            processed_downward_messages: result.processed_downward_messages,
+            hrmp_watermark: result.hrmp_watermark,
+            commitments_match: commitments_hash(result) == expected_commitments,
+        }
+    }
+
+    pub fn print(&self, log: &Diagnostics) {
+        log.line(format!(
+            "Head data: {:?} ({} bytes)",
+            self.head_data_hash, self.head_data_size
+        ));
+        match self.new_validation_code_size {
+            Some(size) => log.line(format!("New validation code: {}kb", size / 1024)),
+            None => log.line("New validation code: none"),
+        }
+        log.line(format!("Upward messages: {}", self.upward_messages));
+        log.line(format!("Horizontal messages: {}", self.horizontal_messages));
+        log.line(format!(
+            "Processed downward messages: {}",
+            self.processed_downward_messages
+        ));
+        log.line(format!("Hrmp watermark: {}", self.hrmp_watermark));
+        if self.commitments_match {
+            log.line("Commitments: match the receipt");
+        } else {
+            log.line("Commitments: MISMATCH with the receipt");
+        }
     }
 }
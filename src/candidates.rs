@@ -0,0 +1,75 @@
+use crate::primitives::H256;
+use anyhow::Context as _;
+use std::io::Read as _;
+use std::path::Path;
+use std::str::FromStr as _;
+
+/// Reads candidate hashes from a file, one per line. `-` reads from stdin.
+///
+/// Empty lines and lines starting with `#` are ignored.
+pub fn read_candidates(path: &Path) -> anyhow::Result<Vec<H256>> {
+    let content = if path == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin().read_to_string(&mut content)?;
+        content
+    } else {
+        std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?
+    };
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| H256::from_str(line).with_context(|| format!("invalid candidate hash {line}")))
+        .collect()
+}
+
+/// Writes the candidate hashes to a file, one per line, in the format of [`read_candidates`].
+pub fn write_candidates(path: &Path, candidates: &[H256]) -> anyhow::Result<()> {
+    let content: String = candidates.iter().map(|c| format!("{c:?}\n")).collect();
+    std::fs::write(path, content).with_context(|| format!("failed to write {}", path.display()))?;
+    crate::report::add_output(path);
+    Ok(())
+}
+
+/// Fisher-Yates shuffle driven by a seeded xorshift, so that samples are reproducible.
+pub fn shuffle<T>(items: &mut [T], seed: u64) {
+    // splitmix64 of the seed, since xorshift gets stuck on 0
+    let mut state = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    state = (state ^ (state >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    state = (state ^ (state >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    state ^= state >> 31;
+    if state == 0 {
+        state = 0x9E37_79B9_7F4A_7C15;
+    }
+    let mut next = move || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+    for i in (1..items.len()).rev() {
+        let j = (next() % (i as u64 + 1)) as usize;
+        items.swap(i, j);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shuffled(seed: u64) -> Vec<u32> {
+        let mut items: Vec<u32> = (0..32).collect();
+        shuffle(&mut items, seed);
+        items
+    }
+
+    #[test]
+    fn different_seeds_give_different_orders() {
+        assert_eq!(shuffled(1), shuffled(1));
+        assert_ne!(shuffled(1), shuffled(2));
+        // the seed that zeroed the state before
+        assert_ne!(shuffled(0x9E37_79B9_7F4A_7C15), shuffled(0));
+    }
+}
@@ -0,0 +1,112 @@
+//! Parquet outputs, behind the `parquet` feature.
+
+use crate::output::Compression;
+use crate::{DisputeInitiator, InclusionPlottingPoint};
+use anyhow::Context as _;
+use arrow::array::{ArrayRef, StringArray, UInt32Array};
+use arrow::datatypes::{DataType, Field, Schema};
+use arrow::record_batch::RecordBatch;
+use parquet::arrow::ArrowWriter;
+use parquet::basic::{GzipLevel, ZstdLevel};
+use parquet::file::properties::WriterProperties;
+use std::sync::Arc;
+
+/// Rows that can be written as columns.
+pub trait Columns: Sized {
+    fn schema() -> Schema;
+    fn columns(rows: &[Self]) -> Vec<ArrayRef>;
+}
+
+impl Columns for InclusionPlottingPoint {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("block_num", DataType::UInt32, false),
+            Field::new("blocks", DataType::UInt32, false),
+            Field::new("params", DataType::Utf8, true),
+            Field::new("author", DataType::Utf8, true),
+            Field::new("session_index", DataType::UInt32, true),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(rows.iter().map(|r| r.block_num).collect::<UInt32Array>()),
+            Arc::new(rows.iter().map(|r| r.blocks).collect::<UInt32Array>()),
+            Arc::new(
+                rows.iter()
+                    .map(|r| r.params.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|r| r.author.as_deref())
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|r| r.session_index)
+                    .collect::<UInt32Array>(),
+            ),
+        ]
+    }
+}
+
+impl Columns for DisputeInitiator {
+    fn schema() -> Schema {
+        Schema::new(vec![
+            Field::new("session_index", DataType::UInt32, false),
+            Field::new("account_id", DataType::Utf8, false),
+            Field::new("signature", DataType::Utf8, true),
+        ])
+    }
+
+    fn columns(rows: &[Self]) -> Vec<ArrayRef> {
+        vec![
+            Arc::new(
+                rows.iter()
+                    .map(|r| r.session_index)
+                    .collect::<UInt32Array>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|r| Some(r.account_id.as_str()))
+                    .collect::<StringArray>(),
+            ),
+            Arc::new(
+                rows.iter()
+                    .map(|r| r.signature.as_deref().map(crate::output::encode_bytes))
+                    .collect::<StringArray>(),
+            ),
+        ]
+    }
+}
+
+/// Writes out the rows as a Parquet file, compressing the pages with `compress`.
+///
+/// Returns the path of the file and the number of rows written.
+pub fn write<T: Columns>(
+    path: &str,
+    compress: Compression,
+    rows: &[T],
+) -> anyhow::Result<(String, usize)> {
+    let schema = Arc::new(T::schema());
+    let batch = RecordBatch::try_new(schema.clone(), T::columns(rows))?;
+
+    let compression = match compress {
+        Compression::None => parquet::basic::Compression::UNCOMPRESSED,
+        Compression::Gzip => parquet::basic::Compression::GZIP(GzipLevel::default()),
+        Compression::Zstd => parquet::basic::Compression::ZSTD(ZstdLevel::default()),
+    };
+    let props = WriterProperties::builder()
+        .set_compression(compression)
+        .build();
+
+    let file = std::fs::File::create(path).with_context(|| format!("failed to create {path}"))?;
+    let mut writer = ArrowWriter::try_new(file, schema, Some(props))?;
+    writer.write(&batch)?;
+    writer.close()?;
+
+    eprintln!("Saved the data to {path}");
+    crate::report::add_output(path);
+    Ok((path.to_owned(), rows.len()))
+}
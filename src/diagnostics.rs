@@ -0,0 +1,61 @@
+use anyhow::Context as _;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Write as _;
+use std::path::Path;
+use std::sync::Mutex;
+
+/// The figures of a validation for the summary table of a batch.
+///
+/// Only the first value of each is kept, so that e.g. the `--extra-pvf` runs
+/// don't overwrite the figures of the validation against the code of the receipt.
+#[derive(Clone, Debug, Default)]
+pub struct Figures {
+    pub para_id: Option<u32>,
+    pub pov_size: Option<usize>,
+    pub preparation_ms: Option<u64>,
+    pub execution_ms: Option<u64>,
+}
+
+/// Validation diagnostics, printed to stdout and optionally tee'd into a log file.
+#[derive(Default)]
+pub struct Diagnostics {
+    file: Option<Mutex<File>>,
+    figures: Mutex<Figures>,
+}
+
+impl Diagnostics {
+    /// Creates (or truncates) the log file at `path`, if given.
+    pub fn new(path: Option<&Path>) -> anyhow::Result<Self> {
+        let file = match path {
+            Some(path) => {
+                let file = File::create(path)
+                    .with_context(|| format!("failed to create {}", path.display()))?;
+                Some(Mutex::new(file))
+            }
+            None => None,
+        };
+        Ok(Self {
+            file,
+            figures: Default::default(),
+        })
+    }
+
+    pub fn line(&self, line: impl Display) {
+        println!("{line}");
+        if let Some(file) = &self.file {
+            let mut file = file.lock().expect("poisoned lock");
+            // the log is best effort, the console has it all anyway
+            let _ = writeln!(file, "{line}");
+        }
+    }
+
+    /// Records a figure of the validation, see [`Figures`].
+    pub fn record(&self, f: impl FnOnce(&mut Figures)) {
+        f(&mut self.figures.lock().expect("poisoned lock"));
+    }
+
+    pub fn figures(&self) -> Figures {
+        self.figures.lock().expect("poisoned lock").clone()
+    }
+}
@@ -0,0 +1,180 @@
+use crate::primitives::H256;
+
+#[derive(Clone, Copy, Debug, Default, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ErrorKind {
+    Http,
+    Rpc,
+    Decode,
+    Validation,
+    #[default]
+    Other,
+}
+
+/// Structured context attached to errors, printed with `--json-errors`.
+#[derive(Clone, Debug, Default, serde::Serialize)]
+pub struct ErrorContext {
+    pub kind: ErrorKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<H256>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub candidate: Option<H256>,
+}
+
+impl ErrorContext {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            ..Default::default()
+        }
+    }
+
+    pub fn http(url: &str) -> Self {
+        Self {
+            url: Some(url.to_owned()),
+            ..Self::new(ErrorKind::Http)
+        }
+    }
+
+    pub fn at_block(mut self, block: u32) -> Self {
+        self.block = Some(block);
+        self
+    }
+
+    pub fn at_block_hash(mut self, block_hash: H256) -> Self {
+        self.block_hash = Some(block_hash);
+        self
+    }
+
+    pub fn for_candidate(mut self, candidate: H256) -> Self {
+        self.candidate = Some(candidate);
+        self
+    }
+}
+
+impl std::fmt::Display for ErrorContext {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let what = match self.kind {
+            ErrorKind::Http => "http request",
+            ErrorKind::Rpc => "rpc request",
+            ErrorKind::Decode => "decoding",
+            ErrorKind::Validation => "validation",
+            ErrorKind::Other => "operation",
+        };
+        write!(f, "{what} failed")?;
+        if let Some(url) = &self.url {
+            write!(f, " for {url}")?;
+        }
+        if let Some(block) = self.block {
+            write!(f, " at block {block}")?;
+        }
+        if let Some(block_hash) = self.block_hash {
+            write!(f, " at block {block_hash:?}")?;
+        }
+        if let Some(candidate) = self.candidate {
+            write!(f, " for candidate {candidate:?}")?;
+        }
+        Ok(())
+    }
+}
+
+/// An unsuccessful HTTP response, kept typed so that retries can tell the statuses apart.
+#[derive(Debug)]
+pub struct HttpStatus {
+    pub status: reqwest::StatusCode,
+    pub url: String,
+}
+
+impl std::fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} responded with {}", self.url, self.status)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+fn is_transient_status(status: reqwest::StatusCode) -> bool {
+    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Whether the error is a network failure that may go away on a retry: a connection failure,
+/// a timeout, a 429 or a 5xx. An RPC error response or exceeding `--max-download-bytes`
+/// never is.
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    if crate::report::download_budget_exceeded() {
+        return false;
+    }
+    err.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            e.is_connect() || e.is_timeout() || e.status().is_some_and(is_transient_status)
+        } else if let Some(e) = cause.downcast_ref::<HttpStatus>() {
+            is_transient_status(e.status)
+        } else {
+            cause
+                .downcast_ref::<::subxt::Error>()
+                .is_some_and(crate::subxt::is_transport_error)
+        }
+    })
+}
+
+#[derive(serde::Serialize)]
+struct JsonError<'a> {
+    kind: ErrorKind,
+    message: String,
+    context: Option<&'a ErrorContext>,
+}
+
+/// Formats the error as JSON, using the outermost `ErrorContext` attached to it.
+pub fn to_json(err: &anyhow::Error) -> String {
+    let context = err.downcast_ref::<ErrorContext>();
+    let json = JsonError {
+        kind: context.map(|c| c.kind).unwrap_or_default(),
+        message: format!("{err:#}"),
+        context,
+    };
+    serde_json::to_string(&json).expect("serializing to string doesn't fail; qed")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::StatusCode;
+
+    const URL: &str = "https://pov.data.paritytech.io";
+
+    fn status_error(status: StatusCode) -> anyhow::Error {
+        let url = URL.to_owned();
+        anyhow::Error::new(HttpStatus { status, url }).context(ErrorContext::http(URL))
+    }
+
+    #[test]
+    fn only_throttling_and_server_errors_are_transient() {
+        assert!(is_transient(&status_error(StatusCode::TOO_MANY_REQUESTS)));
+        assert!(is_transient(&status_error(StatusCode::SERVICE_UNAVAILABLE)));
+        assert!(!is_transient(&status_error(StatusCode::NOT_FOUND)));
+        let budget = anyhow::anyhow!("budget exceeded").context(ErrorContext::http(URL));
+        assert!(!is_transient(&budget));
+    }
+
+    fn rpc_error(message: &str) -> anyhow::Error {
+        let message = std::io::Error::new(std::io::ErrorKind::Other, message);
+        let err = ::subxt::Error::Rpc(::subxt::error::RpcError::ClientError(Box::new(message)));
+        anyhow::Error::new(err).context(ErrorContext::new(ErrorKind::Rpc))
+    }
+
+    #[test]
+    fn only_rpc_transport_errors_are_transient() {
+        assert!(is_transient(&rpc_error(
+            "Networking or low-level protocol error: Connection reset by peer"
+        )));
+        assert!(is_transient(&rpc_error("Request timeout")));
+        assert!(!is_transient(&rpc_error(
+            "State already discarded for 0x1c9f…cba5"
+        )));
+        assert!(!is_transient(&rpc_error("Method not found")));
+    }
+}
@@ -0,0 +1,53 @@
+use anyhow::Context as _;
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::path::Path;
+
+/// A vote of a validator in a dispute.
+pub struct Vote {
+    /// The SS58 account id of the validator.
+    pub validator: String,
+    pub candidate: String,
+    pub valid: bool,
+}
+
+/// Writes the votes as a GraphViz DOT graph of validators and the candidates they voted on.
+///
+/// Edges are green for valid and red for invalid votes. Gephi can import the file as is.
+pub fn write_dot(path: &Path, votes: &[Vote]) -> anyhow::Result<()> {
+    let validators: BTreeSet<&str> = votes.iter().map(|v| v.validator.as_str()).collect();
+    let candidates: BTreeSet<&str> = votes.iter().map(|v| v.candidate.as_str()).collect();
+
+    let mut out = String::new();
+    let _ = writeln!(out, "digraph disputes {{");
+    let _ = writeln!(out, "  node [style=filled];");
+    for validator in validators {
+        let _ = writeln!(
+            out,
+            "  \"{validator}\" [shape=ellipse, fillcolor=lightblue, kind=validator];"
+        );
+    }
+    for candidate in candidates {
+        let _ = writeln!(
+            out,
+            "  \"{candidate}\" [shape=box, fillcolor=lightgrey, kind=candidate];"
+        );
+    }
+    for vote in votes {
+        let (color, kind) = if vote.valid {
+            ("green", "valid")
+        } else {
+            ("red", "invalid")
+        };
+        let _ = writeln!(
+            out,
+            "  \"{}\" -> \"{}\" [color={color}, kind={kind}];",
+            vote.validator, vote.candidate
+        );
+    }
+    let _ = writeln!(out, "}}");
+
+    std::fs::write(path, out).with_context(|| format!("failed to write {}", path.display()))?;
+    crate::report::add_output(path);
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use anyhow::Context as _;
+use std::path::Path;
+use std::sync::OnceLock;
+
+static CLIENT: OnceLock<reqwest::Client> = OnceLock::new();
+
+/// The User-Agent sent unless overridden with `--user-agent`.
+pub const DEFAULT_USER_AGENT: &str = concat!("kuddelmuddel/", env!("CARGO_PKG_VERSION"));
+
+/// Configures the shared client.
+///
+/// The responses are requested gzip compressed, with `Accept-Encoding: gzip`,
+/// and transparently decompressed, which matters for the large PoVs and event pages.
+///
+/// With `identity`, the client authenticates with the PEM encoded certificate
+/// and PKCS#8 key, for the mTLS-protected mirrors. Fails if the files can't be loaded,
+/// so that we don't start making requests that would be rejected anyway.
+pub fn init(user_agent: &str, identity: Option<(&Path, &Path)>) -> anyhow::Result<()> {
+    let mut builder = reqwest::Client::builder().user_agent(user_agent).gzip(true);
+    if let Some((cert, key)) = identity {
+        let cert =
+            std::fs::read(cert).with_context(|| format!("failed to read {}", cert.display()))?;
+        let key =
+            std::fs::read(key).with_context(|| format!("failed to read {}", key.display()))?;
+        let identity = reqwest::Identity::from_pkcs8_pem(&cert, &key)
+            .context("failed to load the TLS client certificate and key")?;
+        builder = builder.identity(identity);
+    }
+    CLIENT
+        .set(builder.build()?)
+        .map_err(|_| anyhow::anyhow!("the http client is already initialized"))
+}
+
+/// The client shared by all the requests to Subscan and povs.today.
+pub fn client() -> reqwest::Client {
+    CLIENT
+        .get_or_init(|| {
+            reqwest::Client::builder()
+                .user_agent(DEFAULT_USER_AGENT)
+                .gzip(true)
+                .build()
+                .expect("the default client is valid; qed")
+        })
+        .clone()
+}
@@ -1,12 +1,40 @@
-use crate::primitives::{AccountId32, SessionIndex, H256};
+use crate::diagnostics::Diagnostics;
+use crate::primitives::{
+    AvailableData, CandidateReceipt, SessionIndex, ValidationCode, ValidationCodeHash,
+    ValidatorIndex, H256,
+};
+use crate::subscan::events::inclusion::EventId;
 
+use anyhow::Context as _;
 use clap::{Parser, Subcommand};
-use std::path::PathBuf;
+use parity_scale_codec::Encode as _;
+use polkadot_node_core_pvf::ValidationHost;
+use std::collections::{btree_map::Entry, BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Instant;
 
+mod analysis;
+mod bundle;
+mod cache;
 mod candidate_validation;
+mod candidates;
+#[cfg(feature = "parquet")]
+mod columnar;
+mod diagnostics;
+mod error;
+mod graph;
+mod http;
+mod names;
+mod output;
+mod plot;
 mod povs_today;
 mod primitives;
+mod progress;
+mod report;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod ss58;
 mod subscan;
 mod subxt;
 
@@ -22,232 +50,2054 @@ const NODE_VERSION: &'static str = env!("SUBSTRATE_CLI_IMPL_VERSION");
 struct Cli {
     #[clap(subcommand)]
     commands: Commands,
+
+    /// Don't show progress bars and spinners.
+    #[clap(long, global = true)]
+    quiet: bool,
+
+    /// Log the latency of every Subscan request and print a summary.
+    #[clap(long, global = true)]
+    timings: bool,
+
+    /// Print the diagnostics of unexpected responses and a summary of the time spent
+    /// waiting because of rate limiting.
+    #[clap(short, long, global = true)]
+    verbose: bool,
+
+    /// Print a JSON report of the run to stdout at the end.
+    #[clap(long, global = true)]
+    report_json: bool,
+
+    /// On failure, print a JSON error to stderr instead of the default formatting.
+    #[clap(long, global = true)]
+    json_errors: bool,
+
+    /// Bypass the on-disk caches: always fetch and never write cache entries.
+    #[clap(long, global = true)]
+    no_cache: bool,
+
+    /// Fetch the entries of the on-disk PoV and validation code caches again and overwrite
+    /// them.
+    ///
+    /// Unlike `--no-cache`, which neither reads nor writes the caches, this updates them for
+    /// the following runs. Identity resolution isn't implemented yet, so there are no cached
+    /// identities to invalidate, and the SS58 encodings are only memoized within a run.
+    #[clap(long, global = true, conflicts_with = "no_cache")]
+    refresh_cache: bool,
+
+    /// Write the JSON outputs and reports in a human-readable form.
+    #[clap(long, global = true)]
+    pretty: bool,
+
+    /// Compress the csv and JSON outputs.
+    #[clap(long, global = true, value_enum, default_value_t = output::Compression::None)]
+    compress: output::Compression,
+
+    /// Instead of the progress bars, print the progress as NDJSON events to stderr,
+    /// e.g. `{"stage":"inclusion-events","done":120,"total":500,"finished":false}`.
+    #[clap(long, global = true)]
+    progress_json: bool,
+
+    /// When to color the progress indicators and other terminal output.
+    #[clap(long, global = true, value_enum, default_value_t = progress::ColorChoice::Auto)]
+    color: progress::ColorChoice,
+
+    /// Encoding of the binary fields, e.g. hashes and signatures, in all the outputs and reports.
+    #[clap(long, global = true, value_enum, default_value_t = output::Encoding::Hex)]
+    output_encoding: output::Encoding,
+
+    /// Show the names of well-known parachains next to their ids in the summaries,
+    /// manifests and output filenames.
+    #[clap(long, global = true)]
+    with_names: bool,
+
+    /// Decode the RPC responses with the SCALE encoded runtime metadata in this file
+    /// instead of the metadata of the node's current runtime.
+    ///
+    /// Useful when the chain was upgraded since the blocks of interest.
+    /// The file can be saved with `subxt metadata --url <rpc-url> --format bytes`.
+    ///
+    /// The storage queries are then not validated against the metadata, so the items they
+    /// decode must have kept their layout: ParaSessionInfo.AccountKeys, Paras.CodeByHash,
+    /// Paras.CurrentCodeHash, ParasDisputes.Disputes, ParasShared.ActiveValidatorKeys,
+    /// Session.CurrentIndex and Session.Validators (see `check-metadata`).
+    #[clap(long, global = true)]
+    metadata_file: Option<PathBuf>,
+
+    /// Stop scanning for events after this many blocks or pages in a row brought no new ones,
+    /// keeping the events found so far. Zero never stops.
+    #[clap(long, global = true, default_value_t = subscan::DEFAULT_STALL_THRESHOLD)]
+    stall_threshold: u32,
+
+    /// Write the metrics of the run to this file in the Prometheus text format.
+    #[clap(long, global = true)]
+    prometheus_out: Option<PathBuf>,
+
+    /// User-Agent header of the Subscan and povs.today requests.
+    #[clap(long, global = true, default_value = http::DEFAULT_USER_AGENT)]
+    user_agent: String,
+
+    /// Subscan module of the backing and inclusion events.
+    ///
+    /// An escape hatch for experts, in case Subscan renames the module. The events
+    /// must have the same shape as the ones of `parainclusion`.
+    #[clap(
+        long,
+        global = true,
+        default_value = "parainclusion",
+        hide_short_help = true
+    )]
+    inclusion_module: String,
+
+    /// Subscan module of the dispute events, see `--inclusion-module`.
+    #[clap(
+        long,
+        global = true,
+        default_value = "parasdisputes",
+        hide_short_help = true
+    )]
+    disputes_module: String,
+
+    /// Subscan call of the initiated dispute events, see `--inclusion-module`.
+    #[clap(
+        long,
+        global = true,
+        default_value = "disputeinitiated",
+        hide_short_help = true
+    )]
+    disputes_call: String,
+
+    /// How many hex characters of the candidate hash, after the `0x`, name the folder
+    /// of the candidate on povs.today, in case its layout changes.
+    #[clap(
+        long,
+        global = true,
+        default_value_t = povs_today::DEFAULT_PREFIX_LEN,
+        value_parser = clap::value_parser!(u8).range(1..=64)
+    )]
+    prefix_len: u8,
+
+    /// PEM encoded client certificate for mTLS-protected Subscan and povs.today mirrors.
+    ///
+    /// Not used for the RPC connection.
+    #[clap(long, global = true, requires = "tls_key")]
+    tls_cert: Option<PathBuf>,
+
+    /// PKCS#8 PEM encoded private key of `--tls-cert`.
+    #[clap(long, global = true, requires = "tls_cert")]
+    tls_key: Option<PathBuf>,
 }
 
-#[derive(Subcommand)]
+#[derive(Subcommand, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
 enum Commands {
     /// Fetches the backing and inclusion events and writes out csv files to `./out/`.
     ///
+    /// Rows are ordered by block number (or as given by `--sort-by`), ties are broken
+    /// by block number, so the same inputs always produce the same files.
+    ///
     /// Example:
     /// ```bash
     /// cargo run -- inclusion --network kusama --para-id 2023 --up-to-block 11324714
     /// ```
-    Inclusion {
+    Inclusion(InclusionCommand),
+    /// Fetches the dispute invalid votes and writes out a csv file to `./out/`.
+    ///
+    /// Rows are ordered by block number, session and validator index,
+    /// so the same inputs always produce the same file.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- disputes --network kusama --up-to-block 11324714 --num-events 200 \
+    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    Disputes(DisputesCommand),
+    /// Given the candidate hash, fetch candidate's available data
+    /// and receipt from `povs.today` and the corresponding validation code
+    /// from the runtime, compile validation code and validate the candidate.
+    ///
+    /// All the data will be cached in the `--cache` folder.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run --release -- validate-candidate --network kusama \
+    ///  --candidate-hash "0x03134f027883df8db3ce71602412d906024c96eaef06cda403c48cfb6661e5a8" \
+    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    ValidateCandidate(ValidateCandidateCommand),
+    /// Prints the statistics of a previously saved inclusion or disputes csv file.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- analyze --input out/11324714-inclusion-2023.csv --json out/inclusion.json
+    /// ```
+    Analyze {
+        /// Path to the csv file produced by `inclusion` or `disputes`.
+        #[clap(long)]
+        input: PathBuf,
+
+        /// Kind of the csv file. Inferred from the header if not specified.
+        #[clap(long, value_enum)]
+        kind: Option<analysis::CsvKind>,
+
+        /// Optionally re-export the data as JSON to this path.
+        #[clap(long)]
+        json: Option<PathBuf>,
+    },
+    /// Packs the cached PoV, receipt and validation code of a candidate
+    /// into a `.tar.gz` bundle with a manifest, to share a reproduction.
+    ///
+    /// The candidate needs to be validated with `validate-candidate` first.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- export-bundle --network kusama \
+    ///  --candidate-hash "0x03134f027883df8db3ce71602412d906024c96eaef06cda403c48cfb6661e5a8"
+    /// ```
+    ExportBundle {
         /// Name of the network, e.g. "kusama".
-        #[clap(long, default_value = "kusama")]
+        ///
+        /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
         network: String,
 
-        /// Parachain ID to be processed.
+        /// Hash of the candidate.
         #[clap(long)]
-        para_id: u32,
+        candidate_hash: H256,
 
-        /// The block number up to which we should
-        /// be fetching events, e.g. 13524714.
+        /// Cache folder storing candidate receipts, available data, validation code.
+        ///
+        /// Default: `./.cache`.
         #[clap(long)]
-        up_to_block: u32,
+        cache: Option<PathBuf>,
 
-        /// How many events to fetch
-        #[clap(long, default_value_t = 500)]
-        num_events: usize,
+        /// Path of the bundle.
+        ///
+        /// Default: `./out/<candidate-hash>.tar.gz`.
+        #[clap(long)]
+        output: Option<PathBuf>,
     },
-    /// Fetches the dispute invalid votes and writes out a csv file to `./out/`.
+    /// Checks that the bundled metadata is compatible with a live node.
     ///
     /// Example:
     /// ```bash
-    /// cargo run -- disputes --network kusama --up-to-block 11324714 --num-events 200 \
-    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// cargo run -- check-metadata --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    CheckMetadata {
+        /// Url for an RPC node to fetch the metadata from.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: String,
+    },
+    /// Prints the JSON schema of an output, to validate or generate code against.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- print-schema report
     /// ```
-    Disputes {
+    PrintSchema {
+        /// Which output to print the schema of.
+        #[clap(value_enum)]
+        kind: output::SchemaKind,
+    },
+    /// Checks that Subscan, povs.today and the RPC node are reachable,
+    /// reporting the latency or the error of each.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- doctor --network kusama --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    Doctor {
+        /// Name of the network, e.g. "kusama".
+        ///
+        /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
+        network: String,
+
+        /// Url for an RPC node to connect to. The RPC check is skipped if not set.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: Option<String>,
+    },
+    /// Fetches and validates a known-good candidate of the network, to check that
+    /// `povs.today`, the RPC node and the PVF workers all work in this environment.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run --release -- self-test --network kusama --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    SelfTest {
         /// Name of the network, e.g. "kusama".
-        #[clap(long, default_value = "kusama")]
+        ///
+        /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
         network: String,
 
-        /// How many events to fetch.
-        #[clap(long, default_value_t = 100)]
-        num_events: usize,
+        /// Url for an RPC node to query the runtime.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: String,
+    },
+
+    /// Finds the most recent candidate of the parachain included up to the block
+    /// and validates it, like `validate-candidate` does.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run --release -- validate-at --network kusama --para-id 2023 \
+    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    ValidateAt {
+        /// Name of the network, e.g. "kusama".
+        ///
+        /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
+        network: String,
 
-        /// The block number up to which we should
-        /// be fetching events, e.g. 13524714.
+        /// Parachain ID.
         #[clap(long)]
-        up_to_block: u32,
+        para_id: u32,
 
-        /// Url for an RPC node to query the historical data.
+        /// Look for the candidate included at or before this block.
         ///
-        /// Example:
-        /// `wss://kusama-rpc.polkadot.io:443` or `http://localhost:9933/`
+        /// Defaults to the finalized head of `--rpc-url`.
         #[clap(long)]
+        up_to_block: Option<u32>,
+
+        /// Url for an RPC node to query the runtime.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
         rpc_url: String,
+
+        /// Cache folder storing candidate receipts, available data, validation code.
+        ///
+        /// Default: `./.cache`.
+        #[clap(long)]
+        cache: Option<PathBuf>,
     },
-    /// Given the candidate hash, fetch candidate's available data
-    /// and receipt from `povs.today` and the corresponding validation code
-    /// from the runtime, compile validation code and validate the candidate.
+
+    /// Compares two validation code blobs, e.g. to check whether two candidates
+    /// ran the exact same runtime.
     ///
-    /// All the data will be cached in the `--cache` folder.
+    /// Example:
+    /// ```bash
+    /// cargo run -- diff-pvf <code-hash> path/to/pvf.scale \
+    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    DiffPvf {
+        /// Validation code hash of a cached or on-chain PVF,
+        /// or a file with the SCALE encoded validation code.
+        #[clap(value_parser = parse_pvf_source)]
+        a: PvfSource,
+
+        /// The PVF to compare against, like the first one.
+        #[clap(value_parser = parse_pvf_source)]
+        b: PvfSource,
+
+        /// Url for an RPC node to fetch the PVFs that are not cached at its finalized head.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: Option<String>,
+
+        /// Cache folder storing candidate receipts, available data, validation code.
+        ///
+        /// Default: `./.cache`.
+        #[clap(long)]
+        cache: Option<PathBuf>,
+    },
+
+    /// Fetches the validation code of a parachain that is current at a block,
+    /// without a candidate at hand.
     ///
     /// Example:
     /// ```bash
-    /// cargo run --release -- validate-candidate --network kusama \
-    ///  --candidate-hash "0x03134f027883df8db3ce71602412d906024c96eaef06cda403c48cfb6661e5a8" \
+    /// cargo run -- get-pvf --para-id 2023 --at-block 11324714 --output pvf.scale \
+    ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
+    /// ```
+    GetPvf {
+        /// Parachain ID.
+        #[clap(long)]
+        para_id: u32,
+
+        /// Block to look up the validation code at. Defaults to the finalized head.
+        #[clap(long)]
+        at_block: Option<u32>,
+
+        /// Url for an RPC node to query the runtime.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: String,
+
+        /// Also write the SCALE encoded validation code to this file,
+        /// e.g. for `validate-candidate --pvf-file`.
+        #[clap(long)]
+        output: Option<PathBuf>,
+
+        /// Cache folder storing candidate receipts, available data, validation code.
+        ///
+        /// Default: `./.cache`.
+        #[clap(long)]
+        cache: Option<PathBuf>,
+    },
+
+    /// Compiles the validation code of the given code or candidate hashes into the
+    /// artifact cache of the PVF host, without executing anything, so that a following
+    /// `validate-candidate` sweep doesn't have to.
+    ///
+    /// Example:
+    /// ```bash
+    /// cargo run -- warm-pvfs --hashes-file candidates.txt --from-candidates \
     ///  --rpc-url "wss://kusama-rpc.polkadot.io:443"
     /// ```
-    ValidateCandidate {
+    WarmPvfs {
         /// Name of the network, e.g. "kusama".
-        #[clap(long, default_value = "kusama")]
+        ///
+        /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
         network: String,
 
-        /// Url for an RPC node to query the runtime.
-        ///
-        /// Example:
-        /// `wss://kusama-rpc.polkadot.io:443` or `http://localhost:9933/`
-        #[clap(long)]
-        rpc_url: String,
+        /// File with validation code hashes, one per line. Use `-` for stdin.
+        #[clap(long)]
+        hashes_file: PathBuf,
+
+        /// The hashes are candidate hashes, whose validation code hashes
+        /// are looked up in their receipts on `povs.today`.
+        #[clap(long)]
+        from_candidates: bool,
+
+        /// Url for an RPC node to query the validation code.
+        ///
+        /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+        #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+        rpc_url: String,
+
+        /// Cache folder storing candidate receipts, available data, validation code.
+        ///
+        /// Default: `./.cache`.
+        #[clap(long)]
+        cache: Option<PathBuf>,
+    },
+
+    // These are needed for candidate validation:
+    #[allow(missing_docs)]
+    #[clap(name = "prepare-worker", hide = true)]
+    PvfPrepareWorker(ValidationWorkerCommand),
+
+    #[allow(missing_docs)]
+    #[clap(name = "execute-worker", hide = true)]
+    PvfExecuteWorker(ValidationWorkerCommand),
+}
+
+#[derive(Debug, Parser, serde::Serialize)]
+pub struct InclusionCommand {
+    /// Name of the network, e.g. "kusama".
+    ///
+    /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+    #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
+    pub network: String,
+
+    /// Parachain ID to be processed.
+    #[clap(long, required_unless_present = "all_paras")]
+    pub para_id: Option<u32>,
+
+    /// Kinds of events to process, can be repeated.
+    ///
+    /// The backing and inclusion times need both `CandidateBacked` and `CandidateIncluded`,
+    /// `CandidateTimedOut` events are written out to `out/<up-to-block>-timeouts-<para-id>.csv`.
+    #[clap(
+        long = "events",
+        value_enum,
+        default_values_t = [EventId::CandidateBacked, EventId::CandidateIncluded]
+    )]
+    pub events: Vec<EventId>,
+
+    /// Process every parachain with events in the scanned blocks,
+    /// writing out separate csv files for each.
+    ///
+    /// The parachains are scanned together, block by block, rather than one after another,
+    /// so a busy parachain can't starve a quiet one. Note that `--num-events` counts the
+    /// events of all of them though, use `--max-blocks-scanned` to cover the same window.
+    #[clap(long, conflicts_with_all = ["para_id", "plot"])]
+    pub all_paras: bool,
+
+    /// The block number up to which we should
+    /// be fetching events, e.g. 13524714.
+    ///
+    /// Defaults to the finalized head of `--rpc-url`.
+    #[clap(long, required_unless_present = "rpc_url")]
+    pub up_to_block: Option<u32>,
+
+    /// Url for an RPC node to query the finalized head when `--up-to-block` is omitted.
+    ///
+    /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+    #[clap(long, env = "KUDDELMUDDEL_RPC_URL")]
+    pub rpc_url: Option<String>,
+
+    /// How many events to fetch
+    #[clap(long, default_value_t = 500)]
+    pub num_events: usize,
+
+    /// Once `--num-events` is reached, keep fetching until the last block
+    /// is fully consumed. The output may then contain more than `--num-events` events.
+    #[clap(long)]
+    pub complete_blocks: bool,
+
+    /// Stop scanning after this many blocks, even if `--num-events` isn't reached.
+    #[clap(long, value_name = "BLOCKS")]
+    pub max_blocks_scanned: Option<u32>,
+
+    /// Add the author of each block as a column to the csv output, resolved via `--rpc-url`.
+    #[clap(long, requires = "rpc_url")]
+    pub with_author: bool,
+
+    /// Add the session of each block as a column to the csv output, resolved via `--rpc-url`,
+    /// and report the session changes within the scanned window.
+    #[clap(long, requires = "rpc_url")]
+    pub with_session: bool,
+
+    /// Add the raw Subscan event params as a column to the csv output.
+    #[clap(long)]
+    pub include_raw_params: bool,
+
+    /// Only compute and write out the backing times.
+    #[clap(long, conflicts_with = "included_only")]
+    pub include_backed_only: bool,
+
+    /// Only compute and write out the inclusion times.
+    #[clap(long)]
+    pub included_only: bool,
+
+    /// Error on duplicate events instead of silently dropping them.
+    #[clap(long)]
+    pub strict_dedup: bool,
+
+    /// Order of the rows in the csv output.
+    #[clap(long, value_enum, default_value_t = SortBy::Block)]
+    pub sort_by: SortBy,
+
+    /// Leave out the points with a gap of less than this many blocks from the csv output.
+    ///
+    /// The summary still covers all the points.
+    #[clap(long, value_name = "N")]
+    pub min_gap: Option<u32>,
+
+    /// Name the outputs `out/<stem>-backing.csv` etc. instead of
+    /// `out/<up-to-block>-backing-<para-id>.csv` etc.
+    #[clap(long, value_name = "STEM", value_parser = output::parse_stem)]
+    pub output_name: Option<String>,
+
+    /// Append to the existing csv files instead of overwriting them.
+    ///
+    /// Meant for scraping adjacent windows with the same `--output-name`.
+    /// Warns about rows that are already in the files.
+    #[clap(long)]
+    pub append: bool,
+
+    /// Format of the backing and inclusion files. The other outputs are always csv.
+    #[clap(long, value_enum, default_value_t = output::Format::Csv)]
+    pub format: output::Format,
+
+    /// Also append the backing and inclusion points to this SQLite database,
+    /// tagged with a row of the run in the `runs` table.
+    ///
+    /// Requires the `sqlite` feature.
+    #[clap(long, value_name = "PATH")]
+    pub sqlite: Option<PathBuf>,
+
+    /// Also scan the same number of events up to this block and print how the
+    /// statistics differ between the two scans, e.g. before and after an upgrade.
+    #[clap(long, value_name = "BLOCK", conflicts_with = "all_paras")]
+    pub compare_block: Option<u32>,
+
+    /// Also render the written points as a chart to this `.png` file.
+    #[clap(long, value_name = "PATH")]
+    pub plot: Option<PathBuf>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortBy {
+    /// By block number, ascending.
+    Block,
+    /// By gap size, largest first.
+    Gap,
+}
+
+#[derive(Debug, Parser, serde::Serialize)]
+pub struct DisputesCommand {
+    /// Name of the network, e.g. "kusama".
+    ///
+    /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+    #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
+    pub network: String,
+
+    /// How many events to fetch.
+    #[clap(long, default_value_t = 100)]
+    pub num_events: usize,
+
+    /// The block number up to which we should
+    /// be fetching events, e.g. 13524714.
+    ///
+    /// Defaults to the finalized head of `--rpc-url`.
+    #[clap(long, required_unless_present = "rpc_url")]
+    pub up_to_block: Option<u32>,
+
+    /// Url for an RPC node to query the historical data.
+    ///
+    /// Example:
+    /// `wss://kusama-rpc.polkadot.io:443` or `http://localhost:9933/`
+    ///
+    /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+    #[clap(
+        long,
+        env = "KUDDELMUDDEL_RPC_URL",
+        required_unless_present = "count_only"
+    )]
+    pub rpc_url: Option<String>,
+
+    /// Start fetching the events from this page of 100 events, skipping the
+    /// previous ones, e.g. to continue an interrupted scrape.
+    #[clap(long, default_value_t = 0)]
+    pub start_page: u32,
+
+    /// Only count the initiated disputes and print their block distribution,
+    /// skipping the extrinsic and account keys resolution.
+    ///
+    /// The filters need the resolved extrinsics, so the count is always of all the disputes.
+    #[clap(long)]
+    pub count_only: bool,
+
+    /// Resolve the canonical block hash of each dispute via the RPC node
+    /// instead of trusting the one reported by Subscan.
+    #[clap(long)]
+    pub canonical_block_hash: bool,
+
+    /// Only keep the disputes concerning this parachain.
+    ///
+    /// The para id of a disputed candidate is looked up in its receipt on `povs.today`.
+    #[clap(long, conflicts_with = "count_only")]
+    pub para_id: Option<u32>,
+
+    /// Only keep the disputes initiated by the validator with this index.
+    ///
+    /// Note that validator indices are per session.
+    #[clap(long, conflicts_with = "count_only")]
+    pub validator_index: Option<ValidatorIndex>,
+
+    /// Add the signatures of the dispute statements to the output.
+    #[clap(long)]
+    pub with_signatures: bool,
+
+    /// Error on duplicate events instead of silently dropping them.
+    #[clap(long)]
+    pub strict_dedup: bool,
+
+    /// Also write the per-session summary to `out/disputes-summary-<network>-<up-to-block>.csv`.
+    #[clap(long)]
+    pub session_summary: bool,
+
+    /// Query the validator account keys of all sessions at this block,
+    /// instead of at the blocks of the disputes reported by Subscan.
+    ///
+    /// The keys of a session are written once when the session starts and are kept for
+    /// the dispute period (6 sessions), so any block in that window returns the same keys.
+    /// This makes only one RPC call per session and doesn't depend on Subscan's block hashes.
+    #[clap(long, conflicts_with = "canonical_block_hash")]
+    pub keys_at_block: Option<u32>,
+
+    /// Leave out the disputes of the sessions whose account keys can't be queried
+    /// because the RPC node pruned the state, instead of failing.
+    ///
+    /// The output is then incomplete, use an archive node to get all of it.
+    #[clap(long, conflicts_with = "count_only")]
+    pub skip_unavailable: bool,
+
+    /// SS58 prefix of the account ids in the output.
+    ///
+    /// Defaults to the prefix of `--network`, or the generic substrate one (42) if unknown.
+    #[clap(long, value_parser = ss58::parse_prefix)]
+    pub ss58_prefix: Option<u16>,
+
+    /// Read the disputes from the `ParasDisputes.Disputes` storage at `--up-to-block`
+    /// instead of Subscan.
+    ///
+    /// Only the disputes of the recent sessions are kept in storage, and every validator
+    /// that voted invalid is reported, not only the ones that initiated the dispute.
+    #[clap(long, conflicts_with = "count_only")]
+    pub from_storage: bool,
+
+    /// Name the outputs `out/<stem>.csv` and `out/<stem>-summary.csv` instead of
+    /// `out/disputes-<network>-<up-to-block>.csv` etc.
+    #[clap(long, value_name = "STEM", value_parser = output::parse_stem)]
+    pub output_name: Option<String>,
+
+    /// Append to the existing csv files instead of overwriting them.
+    ///
+    /// Meant for scraping adjacent windows with the same `--output-name`.
+    /// Warns about rows that are already in the files.
+    #[clap(long, conflicts_with = "count_only")]
+    pub append: bool,
+
+    /// Format of the disputes file. The session summary is always csv.
+    #[clap(long, value_enum, default_value_t = output::Format::Csv)]
+    pub format: output::Format,
+
+    /// Also append the dispute initiators to this SQLite database,
+    /// tagged with a row of the run in the `runs` table.
+    ///
+    /// Requires the `sqlite` feature.
+    #[clap(long, value_name = "PATH", conflicts_with = "count_only")]
+    pub sqlite: Option<PathBuf>,
+
+    /// Also write the votes on the disputed candidates as a GraphViz DOT graph to this path.
+    ///
+    /// Validators and candidates are the nodes, votes are the edges, green for valid and
+    /// red for invalid. The file can be rendered with `dot` or imported into Gephi.
+    ///
+    /// No account keys are resolved for the valid votes: the validators are labelled with
+    /// the keys of their session if an invalid vote resolved them, `<session>/<index>` otherwise.
+    #[clap(long, value_name = "PATH", conflicts_with = "count_only")]
+    pub dot: Option<PathBuf>,
+
+    /// Write the hashes of the disputed candidates to this file, one per line,
+    /// ready for `validate-candidate --candidates-file`.
+    ///
+    /// Disputes whose candidate hash isn't in the Subscan response are left out.
+    #[clap(long, value_name = "PATH", conflicts_with = "count_only")]
+    pub candidates_out: Option<PathBuf>,
+
+    /// Write the Subscan response of every `paraInherent` extrinsic as is to
+    /// `<block>-<extrinsic>.json` in this folder, e.g. to report parsing mismatches.
+    ///
+    /// The responses are written before parsing, so also the ones that fail to parse.
+    #[clap(long, value_name = "DIR", conflicts_with_all = ["count_only", "from_storage"])]
+    pub raw_output: Option<PathBuf>,
+
+    /// Fetch a page of events again after a short delay if it's partial, i.e. has fewer
+    /// events than requested, in case Subscan was still indexing the range.
+    ///
+    /// The last page of the range is partial too, so this costs one more request.
+    #[clap(long, conflicts_with = "from_storage")]
+    pub retry_partial_pages: bool,
+}
+
+#[derive(Debug, Parser, serde::Serialize)]
+pub struct ValidateCandidateCommand {
+    /// Name of the network, e.g. "kusama".
+    ///
+    /// Falls back to `KUDDELMUDDEL_NETWORK` if not provided.
+    #[clap(long, env = "KUDDELMUDDEL_NETWORK", default_value = "kusama")]
+    pub network: String,
+
+    /// Url for an RPC node to query the runtime.
+    ///
+    /// Example:
+    /// `wss://kusama-rpc.polkadot.io:443` or `http://localhost:9933/`
+    ///
+    /// Falls back to `KUDDELMUDDEL_RPC_URL` if not provided.
+    #[clap(
+        long,
+        env = "KUDDELMUDDEL_RPC_URL",
+        required_unless_present = "pvf_file"
+    )]
+    pub rpc_url: Option<String>,
+
+    /// Hash of the candidate.
+    #[clap(long, required_unless_present_any = ["candidates_file", "at_block"])]
+    pub candidate_hash: Option<H256>,
+
+    /// Validate the candidate of `--para-id` included at this block.
+    #[clap(long, requires = "para_id", conflicts_with_all = ["candidate_hash", "candidates_file"])]
+    pub at_block: Option<u32>,
+
+    /// Parachain ID of the candidate included at `--at-block`.
+    #[clap(long, requires = "at_block")]
+    pub para_id: Option<u32>,
+
+    /// File with candidate hashes to validate, one per line. Use `-` for stdin.
+    #[clap(long, conflicts_with = "candidate_hash")]
+    pub candidates_file: Option<PathBuf>,
+
+    /// Validate at most this many candidates from `--candidates-file`.
+    #[clap(long)]
+    pub limit: Option<usize>,
+
+    /// Shuffle the candidates from `--candidates-file` before applying `--limit`.
+    #[clap(long)]
+    pub shuffle: bool,
+
+    /// Seed for `--shuffle`, so that the sample is reproducible.
+    #[clap(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Cache folder storing candidate receipts, available data, validation code.
+    ///
+    /// Default: `./.cache`.
+    #[clap(long)]
+    pub cache: Option<PathBuf>,
+
+    /// Print the candidate's receipt before validating it.
+    #[clap(long)]
+    pub show_receipt: bool,
+
+    /// Erasure code the available data and check the root against the receipt.
+    #[clap(long, requires = "rpc_url")]
+    pub verify_erasure: bool,
+
+    /// Print the candidate's persisted validation data before validating it.
+    #[clap(long)]
+    pub show_validation_data: bool,
+
+    /// Use the PoV block data as is instead of decompressing it,
+    /// e.g. to rule out the decompression when diagnosing a failure.
+    #[clap(long)]
+    pub assume_uncompressed: bool,
+
+    /// Execute the candidate this many times and check that all the results are the same.
+    #[clap(long, value_name = "RUNS")]
+    pub determinism_check: Option<u32>,
+
+    /// Save the full validation result of a successful execution to this file,
+    /// as JSON if it ends with `.json` and SCALE encoded otherwise.
+    #[clap(long, conflicts_with = "candidates_file")]
+    pub save_result: Option<PathBuf>,
+
+    /// Print the complete chain of a validation error and the full error of the PVF worker,
+    /// with backtraces, including the ones of panics in the workers.
+    #[clap(long)]
+    pub verbose_errors: bool,
+
+    /// How many decoded PoVs to keep in memory, so that repeated candidates in a batch
+    /// aren't read from disk and decoded again. Zero disables it.
+    #[clap(long, default_value_t = povs_today::DEFAULT_POV_CACHE_ENTRIES)]
+    pub pov_cache_entries: usize,
+
+    /// How many times to retry fetching from `povs.today` when rate limited.
+    #[clap(long, default_value_t = povs_today::DEFAULT_MAX_RETRIES)]
+    pub max_retries: u32,
+
+    /// How many times to retry fetching the PoV, receipt and validation code
+    /// after a network error. Validation failures are never retried.
+    #[clap(long, default_value_t = 3)]
+    pub fetch_retries: u32,
+
+    /// Read the SCALE encoded available data from this file instead of `povs.today`,
+    /// e.g. `pov.scale` from an `export-bundle`.
+    #[clap(long, requires = "receipt_file", conflicts_with = "candidates_file")]
+    pub pov_file: Option<PathBuf>,
+
+    /// Validate the PoV of this other candidate against the validation code
+    /// of `--candidate-hash`, for differential testing.
+    #[clap(long, value_name = "CANDIDATE_HASH", conflicts_with = "pov_file")]
+    pub pov_from: Option<H256>,
+
+    /// Read the SCALE encoded candidate receipt from this file instead of `povs.today`.
+    #[clap(long, requires = "pov_file")]
+    pub receipt_file: Option<PathBuf>,
+
+    /// Read the SCALE encoded validation code from this file instead of the runtime.
+    #[clap(long, conflicts_with = "candidates_file")]
+    pub pvf_file: Option<PathBuf>,
+
+    /// After validating against the code of the receipt, also execute the PoV against
+    /// the SCALE encoded validation code in this file, e.g. a proposed runtime upgrade.
+    /// Can be repeated.
+    ///
+    /// The results are labelled by the code hash and don't affect the exit status.
+    #[clap(long, value_name = "FILE")]
+    pub extra_pvf: Vec<PathBuf>,
+
+    /// How many candidates of `--candidates-file` to fetch and validate at the same time.
+    ///
+    /// They share the PVF host, which runs each preparation and execution in its own worker
+    /// process. The console output of concurrent validations is interleaved, see `--log-dir`.
+    #[clap(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub max_concurrent_validations: u32,
+
+    /// Also write the summary table of `--candidates-file` to this file,
+    /// as JSON if it ends with `.json` and csv otherwise.
+    #[clap(long, value_name = "PATH", requires = "candidates_file")]
+    pub results_out: Option<PathBuf>,
+
+    /// Abort once the PoVs, receipts and validation code downloaded in total exceed
+    /// this many bytes. Cached data doesn't count.
+    #[clap(long, value_name = "BYTES")]
+    pub max_download_bytes: Option<u64>,
+
+    /// Also write the validation diagnostics (PoV size, timings, result) to this file.
+    #[clap(long, conflicts_with_all = ["candidates_file", "log_dir"])]
+    pub log_file: Option<PathBuf>,
+
+    /// Also write the validation diagnostics to `<candidate-hash>.log` in this folder,
+    /// one file per candidate.
+    #[clap(long)]
+    pub log_dir: Option<PathBuf>,
+}
+
+#[allow(missing_docs)]
+#[derive(Debug, Parser, serde::Serialize)]
+pub struct ValidationWorkerCommand {
+    /// The path to the validation host's socket.
+    #[arg(long)]
+    pub socket_path: String,
+    /// The path to the worker-specific temporary directory.
+    #[arg(long)]
+    pub worker_dir_path: String,
+    /// Calling node implementation version
+    #[arg(long)]
+    pub node_impl_version: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct InclusionEvent {
+    pub block_num: u32,
+    pub para_id: u32,
+    pub event_id: EventId,
+    /// The raw event params as returned by Subscan.
+    pub params: String,
+    /// The SS58 encoded author of the block, with `--with-author`.
+    pub author: Option<String>,
+    /// The session of the block, with `--with-session`.
+    pub session_index: Option<SessionIndex>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, Clone, schemars::JsonSchema)]
+pub struct InclusionPlottingPoint {
+    pub block_num: u32,
+    pub blocks: u32,
+    // serialized as empty fields when missing, to keep the csv columns aligned
+    #[serde(default)]
+    pub params: Option<String>,
+    #[serde(default)]
+    pub author: Option<String>,
+    #[serde(default)]
+    pub session_index: Option<SessionIndex>,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct UnincludedCandidate {
+    pub backed_block_num: u32,
+}
+
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct TimedOutCandidate {
+    pub block_num: u32,
+}
+
+#[derive(serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct DisputeInitiator {
+    pub session_index: SessionIndex,
+    /// SS58 encoded.
+    pub account_id: String,
+    /// Rendered with the `--output-encoding`.
+    #[serde(
+        default,
+        serialize_with = "crate::output::serialize_opt_bytes",
+        deserialize_with = "crate::output::deserialize_opt_bytes"
+    )]
+    #[schemars(with = "Option<String>")]
+    pub signature: Option<Vec<u8>>,
+}
+
+/// Returns `up_to_block` if given, the finalized head of `rpc_url` otherwise.
+///
+/// With both, checks that the block exists, since Subscan just returns empty pages
+/// for the blocks in the future.
+async fn resolve_up_to_block(
+    up_to_block: Option<u32>,
+    rpc_url: Option<String>,
+) -> anyhow::Result<u32> {
+    if let Some(up_to_block) = up_to_block {
+        if let Some(rpc_url) = rpc_url {
+            let head = subxt::finalized_head_number(rpc_url).await?;
+            if up_to_block > head {
+                anyhow::bail!(
+                    "block {up_to_block} doesn't exist yet, the finalized head is {head}"
+                );
+            }
+        }
+        return Ok(up_to_block);
+    }
+    let rpc_url = rpc_url.context("either --up-to-block or --rpc-url is required")?;
+    let head = subxt::finalized_head_number(rpc_url).await?;
+    eprintln!("Using the finalized head {head} as --up-to-block");
+    Ok(head)
+}
+
+async fn handle_inclusion(
+    cmd: InclusionCommand,
+    compress: output::Compression,
+    with_names: bool,
+) -> anyhow::Result<()> {
+    if cmd.append && cmd.format != output::Format::Csv {
+        anyhow::bail!("--append is only supported for csv");
+    }
+    if cmd.sqlite.is_some() && !cfg!(feature = "sqlite") {
+        anyhow::bail!("--sqlite requires building with `--features sqlite`");
+    }
+    let up_to_block = resolve_up_to_block(cmd.up_to_block, cmd.rpc_url.clone()).await?;
+    let limits = subscan::ScanLimits {
+        enough_events: cmd.num_events,
+        complete_blocks: cmd.complete_blocks,
+        max_blocks: cmd.max_blocks_scanned,
+    };
+
+    let mut events = subscan::fetch_inclusion_events(
+        &cmd.network,
+        up_to_block,
+        cmd.para_id,
+        &cmd.events,
+        &limits,
+        cmd.strict_dedup,
+    )
+    .await?;
+    report::add_processed(events.len());
+
+    if cmd.with_author {
+        let rpc_url = cmd
+            .rpc_url
+            .clone()
+            .context("--with-author requires --rpc-url")?;
+        let block_nums = events.iter().map(|e| e.block_num).collect::<BTreeSet<_>>();
+        let authors = subxt::block_authors(rpc_url, block_nums).await?;
+        let mut encoder = ss58::Encoder::new(ss58::network_prefix(&cmd.network));
+        for event in events.iter_mut() {
+            event.author = authors.get(&event.block_num).map(|a| encoder.encode(a));
+        }
+    }
+
+    if cmd.with_session {
+        let rpc_url = cmd
+            .rpc_url
+            .clone()
+            .context("--with-session requires --rpc-url")?;
+        let block_nums = events.iter().map(|e| e.block_num);
+        let sessions = subxt::session_indices(rpc_url, block_nums).await?;
+        let mut previous: Option<(u32, SessionIndex)> = None;
+        for (&block_num, &session) in sessions.iter() {
+            match previous {
+                Some((prev_block, prev_session)) if prev_session != session => eprintln!(
+                    "Session changed from {prev_session} to {session} \
+                     between blocks {prev_block} and {block_num}"
+                ),
+                _ => {}
+            }
+            previous = Some((block_num, session));
+        }
+        for event in events.iter_mut() {
+            event.session_index = sessions.get(&event.block_num).copied();
+        }
+    }
+
+    let mut by_para: BTreeMap<u32, Vec<InclusionEvent>> = BTreeMap::new();
+    for event in events {
+        by_para.entry(event.para_id).or_default().push(event);
+    }
+    let para_ids: Vec<u32> = match cmd.para_id {
+        Some(para_id) => vec![para_id],
+        None => by_para.keys().copied().collect(),
+    };
+
+    if let (Some(compare_block), Some(para_id)) = (cmd.compare_block, cmd.para_id) {
+        let other = subscan::fetch_inclusion_events(
+            &cmd.network,
+            compare_block,
+            cmd.para_id,
+            &cmd.events,
+            &limits,
+            cmd.strict_dedup,
+        )
+        .await?;
+        report::add_processed(other.len());
+
+        let current = by_para.get(&para_id).cloned().unwrap_or_default();
+        let sides = Sides::wanted(&cmd);
+        let current = Timeline::new(current, false, sides);
+        let other = Timeline::new(other, false, sides);
+        // print the older scan first
+        let (before, after) = if compare_block < up_to_block {
+            ((compare_block, other), (up_to_block, current))
+        } else {
+            ((up_to_block, current), (compare_block, other))
+        };
+        let compared = [
+            (
+                sides.backing,
+                "backing",
+                &before.1.backing_times,
+                &after.1.backing_times,
+            ),
+            (
+                sides.inclusion,
+                "inclusion",
+                &before.1.inclusion_times,
+                &after.1.inclusion_times,
+            ),
+        ];
+        for (_, name, before_times, after_times) in compared.into_iter().filter(|c| c.0) {
+            let before_stats = analysis::InclusionStats::compute(before_times);
+            let after_stats = analysis::InclusionStats::compute(after_times);
+            match (before_stats, after_stats) {
+                (Some(b), Some(a)) => {
+                    analysis::print_stats_diff(name, (before.0, &b), (after.0, &a))
+                }
+                _ => eprintln!("Not enough {name} events to compare"),
+            }
+        }
+    }
+
+    std::fs::create_dir_all("out")?;
+
+    let mut index = Vec::new();
+    for para_id in para_ids {
+        let events = by_para.remove(&para_id).unwrap_or_default();
+        process_para(
+            &cmd,
+            up_to_block,
+            para_id,
+            events,
+            compress,
+            with_names,
+            &mut index,
+        )?;
+    }
+    output::update_index(index)?;
+    Ok(())
+}
+
+/// Which of the backing and inclusion times to compute.
+#[derive(Clone, Copy)]
+struct Sides {
+    backing: bool,
+    inclusion: bool,
+}
+
+impl Sides {
+    const BOTH: Self = Self {
+        backing: true,
+        inclusion: true,
+    };
+
+    /// The sides selected with `--include-backed-only` or `--included-only`.
+    fn wanted(cmd: &InclusionCommand) -> Self {
+        Self {
+            backing: !cmd.included_only,
+            inclusion: !cmd.include_backed_only,
+        }
+    }
+}
+
+/// The backing and inclusion times, and the candidates that didn't make it, of a parachain.
+struct Timeline {
+    backing_times: Vec<InclusionPlottingPoint>,
+    inclusion_times: Vec<InclusionPlottingPoint>,
+    unincluded: Vec<UnincludedCandidate>,
+    timeouts: Vec<TimedOutCandidate>,
+}
+
+impl Timeline {
+    /// Pairs up the events of a single parachain, ordered by block number.
+    ///
+    /// The times of the sides that aren't wanted are left empty.
+    fn new(mut events: Vec<InclusionEvent>, include_raw_params: bool, sides: Sides) -> Self {
+        // A pipelined parachain includes a candidate and backs the next one in the same
+        // block, so within a block the inclusions and timeouts must come first: they
+        // conclude the pending candidate, which the backing then replaces.
+        events.sort_by_key(|e| (e.block_num, e.event_id == EventId::CandidateBacked));
+        let mut last_backed = None;
+        let mut last_included = None;
+        // a backed candidate waiting for its inclusion
+        let mut pending_backed = None;
+        let mut backing_times = Vec::new();
+        let mut inclusion_times = Vec::new();
+        let mut unincluded = Vec::new();
+        let mut timeouts = Vec::new();
+
+        for event in events {
+            if event.event_id == EventId::CandidateTimedOut {
+                timeouts.push(TimedOutCandidate {
+                    block_num: event.block_num,
+                });
+                // it's not going to be included
+                pending_backed = None;
+            } else if event.event_id == EventId::CandidateIncluded {
+                let block_num = event.block_num;
+                if let Some(b) = last_backed.filter(|_| sides.inclusion) {
+                    let blocks = block_num.saturating_sub(b);
+                    let params = include_raw_params.then(|| event.params.clone());
+                    inclusion_times.push(InclusionPlottingPoint {
+                        block_num,
+                        blocks,
+                        params,
+                        author: event.author.clone(),
+                        session_index: event.session_index,
+                    });
+                }
+                last_included = Some(block_num);
+                pending_backed = None;
+            } else {
+                let block_num = event.block_num;
+                if let Some(i) = last_included.filter(|_| sides.backing) {
+                    let blocks = block_num.saturating_sub(i);
+                    let params = include_raw_params.then(|| event.params.clone());
+                    backing_times.push(InclusionPlottingPoint {
+                        block_num,
+                        blocks,
+                        params,
+                        author: event.author.clone(),
+                        session_index: event.session_index,
+                    });
+                }
+                // the previous candidate was superseded without being included
+                if let Some(backed_block_num) = pending_backed.replace(block_num) {
+                    unincluded.push(UnincludedCandidate { backed_block_num });
+                }
+                last_backed = Some(block_num);
+            }
+        }
+        // backed at the end of the scanned window, but not included within it
+        if let Some(backed_block_num) = pending_backed {
+            unincluded.push(UnincludedCandidate { backed_block_num });
+        }
+
+        Self {
+            backing_times,
+            inclusion_times,
+            unincluded,
+            timeouts,
+        }
+    }
+}
+
+/// Computes the backing and inclusion times of a parachain and writes them out.
+fn process_para(
+    cmd: &InclusionCommand,
+    up_to_block: u32,
+    para_id: u32,
+    events: Vec<InclusionEvent>,
+    compress: output::Compression,
+    with_names: bool,
+    index: &mut Vec<output::IndexEntry>,
+) -> anyhow::Result<()> {
+    let network = &cmd.network;
+    let sides = Sides::wanted(cmd);
+    let para = names::display(network, para_id, with_names);
+    let para_label = names::file_label(network, para_id, with_names);
+    let para_name = names::manifest_name(network, para_id, with_names);
+    // with `--all-paras`, the stem is shared by all the parachains
+    let stem = cmd.output_name.as_ref().map(|stem| match cmd.para_id {
+        Some(_) => stem.clone(),
+        None => format!("{stem}-{para_label}"),
+    });
+
+    let block_range = report::block_range(events.iter().map(|e| e.block_num));
+    let Timeline {
+        backing_times,
+        inclusion_times,
+        mut unincluded,
+        mut timeouts,
+    } = Timeline::new(events, cmd.include_raw_params, sides);
+
+    let mut plotted = Vec::new();
+    let outputs = [
+        (backing_times, "backing", sides.backing),
+        (inclusion_times, "inclusion", sides.inclusion),
+    ];
+    for (mut data, name, _) in outputs.into_iter().filter(|(_, _, wanted)| *wanted) {
+        if data.is_empty() {
+            eprintln!("No {name} events found for {para}");
+            continue;
+        }
+        let summary_name = match para_name {
+            Some(_) => format!("{name} of {para}"),
+            None => name.to_owned(),
+        };
+        analysis::summarize_inclusion(&summary_name, &data);
+        let stats = analysis::InclusionStats::compute(&data);
+        if let Some(min_gap) = cmd.min_gap {
+            let total = data.len();
+            data.retain(|p| p.blocks >= min_gap);
+            eprintln!(
+                "Filtered out {} of {total} {name} points with a gap below {min_gap}",
+                total - data.len()
+            );
+        }
+        // don't depend on the order in which events were fetched
+        data.sort_by_key(|p| p.block_num);
+        if cmd.plot.is_some() {
+            plotted.push((name, data.clone()));
+        }
+        if cmd.sort_by == SortBy::Gap {
+            // stable, so equal gaps stay in block order
+            data.sort_by(|a, b| b.blocks.cmp(&a.blocks));
+        }
+        if let Some(db) = &cmd.sqlite {
+            let run = output::Run {
+                command: "inclusion",
+                network,
+                up_to_block,
+                params: serde_json::to_value(cmd)?,
+            };
+            output::write_sqlite_inclusion(db, &run, para_id, name, &data)?;
+        }
+        let ext = cmd.format.extension();
+        let out_file = match &stem {
+            Some(stem) => format!("out/{stem}-{name}.{ext}"),
+            None => format!("out/{up_to_block}-{name}-{para_label}.{ext}"),
+        };
+        let (file, rows) = match cmd.format {
+            output::Format::Csv => output::write_csv(&out_file, compress, cmd.append, data)?,
+            output::Format::Parquet => output::write_parquet(&out_file, compress, &data)?,
+        };
+        index.push(output::IndexEntry {
+            file,
+            kind: name.to_owned(),
+            para_id: Some(para_id),
+            para_name: para_name.clone(),
+            rows,
+            block_range,
+            stats,
+        });
+    }
+
+    if let Some(plot) = &cmd.plot {
+        plot::inclusion(
+            plot,
+            &format!("para_id {para} up to block {up_to_block}"),
+            &plotted,
+        )?;
+    }
+
+    println!(
+        "{} candidates backed but not included for {para}",
+        unincluded.len()
+    );
+    for c in unincluded.iter() {
+        println!("backed at {}", c.backed_block_num);
+    }
+    if !unincluded.is_empty() {
+        unincluded.sort_by_key(|c| c.backed_block_num);
+        let csv_file = match &stem {
+            Some(stem) => format!("out/{stem}-unincluded.csv"),
+            None => format!("out/{up_to_block}-unincluded-{para_label}.csv"),
+        };
+        let (file, rows) = output::write_csv(&csv_file, compress, cmd.append, unincluded)?;
+        index.push(output::IndexEntry {
+            file,
+            kind: "unincluded".to_owned(),
+            para_id: Some(para_id),
+            para_name: para_name.clone(),
+            rows,
+            block_range,
+            stats: None,
+        });
+    }
+
+    if cmd.events.contains(&EventId::CandidateTimedOut) {
+        println!("{} candidates timed out for {para}", timeouts.len());
+        if !timeouts.is_empty() {
+            timeouts.sort_by_key(|c| c.block_num);
+            let csv_file = match &stem {
+                Some(stem) => format!("out/{stem}-timeouts.csv"),
+                None => format!("out/{up_to_block}-timeouts-{para_label}.csv"),
+            };
+            let (file, rows) = output::write_csv(&csv_file, compress, cmd.append, timeouts)?;
+            index.push(output::IndexEntry {
+                file,
+                kind: "timeouts".to_owned(),
+                para_id: Some(para_id),
+                para_name,
+                rows,
+                block_range,
+                stats: None,
+            });
+        }
+    }
+    Ok(())
+}
+
+async fn handle_disputes(
+    cmd: DisputesCommand,
+    compress: output::Compression,
+    with_names: bool,
+) -> anyhow::Result<()> {
+    if cmd.sqlite.is_some() && !cfg!(feature = "sqlite") {
+        anyhow::bail!("--sqlite requires building with `--features sqlite`");
+    }
+    let params = serde_json::to_value(&cmd)?;
+    let DisputesCommand {
+        network,
+        num_events,
+        up_to_block,
+        rpc_url,
+        start_page,
+        count_only,
+        canonical_block_hash,
+        para_id,
+        validator_index,
+        with_signatures,
+        strict_dedup,
+        session_summary,
+        keys_at_block,
+        skip_unavailable,
+        ss58_prefix,
+        from_storage,
+        output_name,
+        append,
+        format,
+        sqlite,
+        dot,
+        candidates_out,
+        raw_output,
+        retry_partial_pages,
+    } = cmd;
+    if append && format != output::Format::Csv {
+        anyhow::bail!("--append is only supported for csv");
+    }
+    let up_to_block = resolve_up_to_block(up_to_block, rpc_url.clone()).await?;
+
+    let (rpc_url, votes) = if from_storage {
+        let rpc_url = rpc_url.context("--rpc-url is required with --from-storage")?;
+        let votes = subxt::disputes_at(rpc_url.clone(), up_to_block).await?;
+        report::add_processed(votes.iter().filter(|v| !v.valid).count());
+        (rpc_url, votes)
+    } else {
+        let events = subscan::fetch_disputes_events(
+            &network,
+            up_to_block,
+            num_events,
+            start_page,
+            strict_dedup,
+            retry_partial_pages,
+        )
+        .await?;
+        report::add_processed(events.len());
+
+        if count_only {
+            let mut per_block: BTreeMap<u32, usize> = BTreeMap::new();
+            for event in events.iter() {
+                *per_block.entry(event.block_num).or_default() += 1;
+            }
+            println!(
+                "{} disputes initiated in {} blocks up to block {up_to_block}",
+                events.len(),
+                per_block.len(),
+            );
+            for (block_num, count) in per_block {
+                println!("{block_num}: {count}");
+            }
+            return Ok(());
+        }
+
+        let rpc_url = rpc_url.context("--rpc-url is required unless --count-only is set")?;
+        if let Some(dir) = &raw_output {
+            std::fs::create_dir_all(dir)?;
+        }
+        let votes =
+            subscan::fetch_dispute_initiators(&network, events, raw_output.as_deref()).await?;
+        (rpc_url, votes)
+    };
+    // Only the invalid votes are reported, so only their account keys get resolved, which is
+    // the expensive part. The valid votes are kept for the graph without resolving anything.
+    let (mut initiators, mut valid_votes): (Vec<_>, Vec<_>) =
+        votes.into_iter().partition(|v| !v.valid);
+    if dot.is_none() {
+        valid_votes = Vec::new();
+    }
+    if let Some(validator_index) = validator_index {
+        initiators.retain(|i| i.validator_index == validator_index);
+        eprintln!(
+            "{} disputes initiated by validator index {validator_index}",
+            initiators.len()
+        );
+    }
+    if let Some(para_id) = para_id {
+        let mut para_ids = BTreeMap::new();
+        for candidate_hash in initiators.iter().filter_map(|i| i.candidate_hash) {
+            if let Entry::Vacant(e) = para_ids.entry(candidate_hash) {
+                let receipt = povs_today::fetch_receipt(
+                    &candidate_hash,
+                    &network,
+                    povs_today::DEFAULT_MAX_RETRIES,
+                )
+                .await;
+                match receipt {
+                    Ok(receipt) => {
+                        e.insert(receipt.descriptor.para_id.0);
+                    }
+                    Err(err) => eprintln!("can't find the para id of {candidate_hash:?}: {err:#}"),
+                }
+            }
+        }
+        let total = initiators.len();
+        initiators.retain(|i| {
+            i.candidate_hash
+                .and_then(|c| para_ids.get(&c))
+                .map_or(false, |p| *p == para_id)
+        });
+        eprintln!(
+            "{} out of {total} dispute initiators concern para_id({})",
+            initiators.len(),
+            names::display(&network, para_id, with_names)
+        );
+    }
+    if let Some(path) = &candidates_out {
+        let disputed: BTreeSet<H256> = initiators.iter().filter_map(|i| i.candidate_hash).collect();
+        let disputed: Vec<H256> = disputed.into_iter().collect();
+        candidates::write_candidates(path, &disputed)?;
+        eprintln!(
+            "Wrote {} disputed candidates to {}",
+            disputed.len(),
+            path.display()
+        );
+    }
+    let canonical_hashes = if canonical_block_hash {
+        let block_nums = initiators.iter().map(|i| i.block_num);
+        subxt::canonical_block_hashes(rpc_url.clone(), block_nums).await?
+    } else {
+        BTreeMap::new()
+    };
+    let keys_at = match keys_at_block {
+        Some(block_num) => {
+            let hashes = subxt::canonical_block_hashes(rpc_url.clone(), [block_num]).await?;
+            let hash = hashes
+                .get(&block_num)
+                .copied()
+                .with_context(|| format!("no block hash for --keys-at-block {block_num}"))?;
+            Some(hash)
+        }
+        None => None,
+    };
+    let input = initiators.iter().map(|i| {
+        if let Some(keys_at) = keys_at {
+            return (i.session_index, keys_at);
+        }
+        let subscan_hash: H256 = FromStr::from_str(&i.block_hash).expect("valid block_hash");
+        let block_hash = match canonical_hashes.get(&i.block_num) {
+            Some(canonical) if *canonical != subscan_hash => {
+                eprintln!(
+                    "block {}: Subscan reported {subscan_hash:?}, canonical is {canonical:?}",
+                    i.block_num
+                );
+                *canonical
+            }
+            _ => subscan_hash,
+        };
+        (i.session_index, block_hash)
+    });
+    let account_map = subxt::historical_account_keys(rpc_url, input, skip_unavailable).await?;
+    if let Some(block_num) = keys_at_block {
+        let sessions = initiators.iter().map(|i| i.session_index);
+        if let Some(missing) = sessions.filter(|s| !account_map.contains_key(s)).min() {
+            anyhow::bail!(
+                "the account keys of session {missing} are not stored at block {block_num}, \
+                 pick a block within the dispute period of the session"
+            );
+        }
+    }
+    let missing: BTreeSet<SessionIndex> = initiators
+        .iter()
+        .map(|i| i.session_index)
+        .filter(|s| !account_map.contains_key(s))
+        .collect();
+    if !missing.is_empty() {
+        if !skip_unavailable {
+            anyhow::bail!("no account keys found for the sessions {missing:?}");
+        }
+        let total = initiators.len();
+        initiators.retain(|i| !missing.contains(&i.session_index));
+        eprintln!(
+            "Warning: left out {} of {total} disputes of the sessions {missing:?} without \
+             account keys, the output is incomplete",
+            total - initiators.len()
+        );
+    }
+
+    let mut encoder =
+        ss58::Encoder::new(ss58_prefix.unwrap_or_else(|| ss58::network_prefix(&network)));
 
-        /// Hash of the candidate.
-        #[clap(long)]
-        candidate_hash: H256,
+    // don't depend on the order in which things were fetched
+    initiators.sort_by_key(|i| (i.block_num, i.session_index, i.validator_index));
 
-        /// Cache folder storing candidate receipts, available data, validation code.
-        ///
-        /// Default: `./.cache`.
-        #[clap(long)]
-        cache: Option<PathBuf>,
-    },
+    if let Some(path) = &dot {
+        let disputed: BTreeSet<H256> = initiators.iter().filter_map(|i| i.candidate_hash).collect();
+        let votes: Vec<graph::Vote> = initiators
+            .iter()
+            .chain(valid_votes.iter())
+            .filter_map(|v| {
+                let candidate = v.candidate_hash.filter(|c| disputed.contains(c))?;
+                let account = account_map
+                    .get(&v.session_index)
+                    .and_then(|keys| keys.get(v.validator_index as usize));
+                let validator = match account {
+                    Some(account) => encoder.encode(account),
+                    None => format!("{}/{}", v.session_index, v.validator_index),
+                };
+                Some(graph::Vote {
+                    validator,
+                    candidate: format!("{candidate:?}"),
+                    valid: v.valid,
+                })
+            })
+            .collect();
+        graph::write_dot(path, &votes)?;
+        eprintln!("Wrote {} votes to {}", votes.len(), path.display());
+    }
 
-    // These are needed for candidate validation:
-    #[allow(missing_docs)]
-    #[clap(name = "prepare-worker", hide = true)]
-    PvfPrepareWorker(ValidationWorkerCommand),
+    let sessions = analysis::summarize_sessions(&initiators);
+    for s in sessions.iter() {
+        eprintln!(
+            "session {}: {} disputes, {} invalid voters, blocks {}-{}",
+            s.session_index, s.disputes, s.invalid_voters, s.first_block, s.last_block
+        );
+    }
+    let block_range = report::block_range(initiators.iter().map(|i| i.block_num));
+    let initiators: Vec<DisputeInitiator> = initiators
+        .into_iter()
+        .map(|i| DisputeInitiator {
+            session_index: i.session_index,
+            // TODO: handle missing keys
+            account_id: encoder.encode(&account_map[&i.session_index][i.validator_index as usize]),
+            signature: i.signature.filter(|_| with_signatures),
+        })
+        .collect();
 
-    #[allow(missing_docs)]
-    #[clap(name = "execute-worker", hide = true)]
-    PvfExecuteWorker(ValidationWorkerCommand),
+    analysis::summarize_disputes(&initiators);
+
+    std::fs::create_dir_all("out")?;
+
+    let ext = format.extension();
+    let out_file = match (&output_name, para_id) {
+        (Some(stem), _) => format!("out/{stem}.{ext}"),
+        (None, Some(para_id)) => {
+            let para_label = names::file_label(&network, para_id, with_names);
+            format!("out/disputes-{network}-{up_to_block}-{para_label}.{ext}")
+        }
+        (None, None) => format!("out/disputes-{network}-{up_to_block}.{ext}"),
+    };
+    if let Some(db) = &sqlite {
+        let run = output::Run {
+            command: "disputes",
+            network: &network,
+            up_to_block,
+            params,
+        };
+        output::write_sqlite_disputes(db, &run, &initiators)?;
+    }
+    let (file, rows) = match format {
+        output::Format::Csv => output::write_csv(&out_file, compress, append, initiators)?,
+        output::Format::Parquet => output::write_parquet(&out_file, compress, &initiators)?,
+    };
+    let para_name = para_id.and_then(|p| names::manifest_name(&network, p, with_names));
+    let mut index = vec![output::IndexEntry {
+        file,
+        kind: "disputes".to_owned(),
+        para_id,
+        para_name: para_name.clone(),
+        rows,
+        block_range,
+        stats: None,
+    }];
+    if session_summary {
+        let csv_file = match &output_name {
+            Some(stem) => format!("out/{stem}-summary.csv"),
+            None => format!("out/disputes-summary-{network}-{up_to_block}.csv"),
+        };
+        let (file, rows) = output::write_csv(&csv_file, compress, append, sessions)?;
+        index.push(output::IndexEntry {
+            file,
+            kind: "disputes-summary".to_owned(),
+            para_id,
+            para_name,
+            rows,
+            block_range,
+            stats: None,
+        });
+    }
+    output::update_index(index)?;
+    Ok(())
 }
 
-#[allow(missing_docs)]
-#[derive(Debug, Parser)]
-pub struct ValidationWorkerCommand {
-    /// The path to the validation host's socket.
-    #[arg(long)]
-    pub socket_path: String,
-    /// The path to the worker-specific temporary directory.
-    #[arg(long)]
-    pub worker_dir_path: String,
-    /// Calling node implementation version
-    #[arg(long)]
-    pub node_impl_version: String,
+fn handle_analyze(
+    input: PathBuf,
+    kind: Option<analysis::CsvKind>,
+    json: Option<PathBuf>,
+    compress: output::Compression,
+    pretty: bool,
+) -> anyhow::Result<()> {
+    let kind = match kind {
+        Some(kind) => kind,
+        None => analysis::infer_kind(&input)?,
+    };
+
+    match kind {
+        analysis::CsvKind::Inclusion => {
+            let data: Vec<InclusionPlottingPoint> = analysis::read_csv(&input)?;
+            let name = input
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            analysis::summarize_inclusion(&name, &data);
+            if let Some(json) = json {
+                analysis::write_json(&json, &data, compress, pretty)?;
+            }
+        }
+        analysis::CsvKind::Disputes => {
+            let data: Vec<DisputeInitiator> = analysis::read_csv(&input)?;
+            analysis::summarize_disputes(&data);
+            if let Some(json) = json {
+                analysis::write_json(&json, &data, compress, pretty)?;
+            }
+        }
+    }
+    Ok(())
 }
 
-#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
-pub struct InclusionEvent {
-    pub block_num: u32,
-    pub para_id: u32,
-    pub included: bool,
+async fn handle_check_metadata(rpc_url: String) -> anyhow::Result<()> {
+    let changed = subxt::check_metadata(rpc_url).await?;
+    if changed.is_empty() {
+        println!("compatible");
+        return Ok(());
+    }
+    for item in changed.iter() {
+        println!("changed: {item}");
+    }
+    anyhow::bail!(
+        "{} storage items changed, the bundled metadata needs regenerating",
+        changed.len()
+    )
 }
 
-#[derive(serde::Serialize, Clone, Copy)]
-pub struct InclusionPlottingPoint {
-    pub block_num: u32,
-    pub blocks: u32,
+/// A PVF to load by its validation code hash, or from a file.
+#[derive(Clone, Debug, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+enum PvfSource {
+    Hash(H256),
+    File(PathBuf),
 }
 
-#[derive(serde::Serialize)]
-pub struct DisputeInitiator {
-    pub session_index: SessionIndex,
-    pub account_id: AccountId32,
+fn parse_pvf_source(s: &str) -> Result<PvfSource, String> {
+    match s.strip_prefix("0x") {
+        Some(hex) if hex.len() == 64 => H256::from_str(s)
+            .map(PvfSource::Hash)
+            .map_err(|e| format!("invalid validation code hash {s}: {e}")),
+        _ => Ok(PvfSource::File(PathBuf::from(s))),
+    }
 }
 
-async fn handle_inclusion(
-    network: String,
+async fn load_pvf(
+    source: &PvfSource,
+    pvfs_path: &Path,
+    rpc_url: Option<&str>,
+    no_cache: bool,
+) -> anyhow::Result<ValidationCode> {
+    let code_hash = match source {
+        PvfSource::File(path) => return decode_file(path),
+        PvfSource::Hash(code_hash) => *code_hash,
+    };
+    let cached = !no_cache && cache::is_cached(&pvfs_path.join(format!("{code_hash:?}")));
+    // the block is only used to fetch the code, a cached one is read as is
+    let (rpc_url, at) = if cached {
+        (rpc_url.unwrap_or_default().to_owned(), H256::zero())
+    } else {
+        let rpc_url = rpc_url
+            .with_context(|| format!("{code_hash:?} is not cached, --rpc-url is required"))?;
+        let at = subxt::finalized_head_hash(rpc_url.to_owned()).await?;
+        (rpc_url.to_owned(), at)
+    };
+    let code_hash = ValidationCodeHash(code_hash);
+    subxt::validation_code_by_hash(pvfs_path, rpc_url, code_hash, at, no_cache).await
+}
+
+async fn handle_get_pvf(
     para_id: u32,
-    up_to_block: u32,
-    num_events: usize,
+    at_block: Option<u32>,
+    rpc_url: String,
+    output: Option<PathBuf>,
+    cache: Option<PathBuf>,
+    no_cache: bool,
 ) -> anyhow::Result<()> {
-    let events =
-        subscan::fetch_inclusion_events(&network, up_to_block, para_id, num_events).await?;
-
-    let mut last_backed = None;
-    let mut last_included = None;
-    let mut backing_times = Vec::new();
-    let mut inclusion_times = Vec::new();
-
-    for event in events.into_iter().filter(|e| e.para_id == para_id) {
-        if event.included {
-            let block_num = event.block_num;
-            if let Some(b) = last_backed {
-                let blocks = block_num.saturating_sub(b);
-                inclusion_times.push(InclusionPlottingPoint { block_num, blocks });
-            }
-            last_included = Some(block_num);
-        } else {
-            let block_num = event.block_num;
-            if let Some(i) = last_included {
-                let blocks = block_num.saturating_sub(i);
-                backing_times.push(InclusionPlottingPoint { block_num, blocks });
-            }
-            last_backed = Some(block_num);
-        }
+    let pvfs_path = cache
+        .unwrap_or_else(|| PathBuf::from(".cache"))
+        .join("pvfs");
+    std::fs::create_dir_all(&pvfs_path)?;
+
+    let block_num = resolve_up_to_block(at_block, Some(rpc_url.clone())).await?;
+    let (code_hash, pvf) =
+        subxt::validation_code_at(&pvfs_path, rpc_url, para_id, block_num, no_cache).await?;
+    let code = candidate_validation::decompress_pvf(&pvf)?;
+    println!(
+        "para_id {para_id} at block {block_num}: code hash {:?}, \
+         {} bytes compressed, {} bytes decompressed",
+        code_hash.0,
+        pvf.0.len(),
+        code.len()
+    );
+
+    if let Some(output) = output {
+        std::fs::write(&output, pvf.encode())
+            .with_context(|| format!("failed to write {}", output.display()))?;
+        eprintln!("Saved the validation code to {}", output.display());
+        report::add_output(output);
     }
+    Ok(())
+}
 
-    std::fs::create_dir_all("out")?;
+async fn handle_warm_pvfs(
+    network: String,
+    hashes_file: PathBuf,
+    from_candidates: bool,
+    rpc_url: String,
+    cache: Option<PathBuf>,
+    no_cache: bool,
+) -> anyhow::Result<()> {
+    let pvfs_path = cache
+        .unwrap_or_else(|| PathBuf::from(".cache"))
+        .join("pvfs");
+    std::fs::create_dir_all(&pvfs_path)?;
 
-    for (data, name) in [(backing_times, "backing"), (inclusion_times, "inclusion")] {
-        if data.is_empty() {
-            eprintln!("No {name} events found for {para_id}");
-            continue;
+    let hashes = candidates::read_candidates(&hashes_file)?;
+    // the code hashes and the blocks to query them at
+    let codes: BTreeMap<H256, H256> = if from_candidates {
+        let mut codes = BTreeMap::new();
+        for candidate_hash in &hashes {
+            let receipt = povs_today::fetch_receipt(
+                candidate_hash,
+                &network,
+                povs_today::DEFAULT_MAX_RETRIES,
+            )
+            .await?;
+            let descriptor = receipt.descriptor;
+            codes
+                .entry(descriptor.validation_code_hash.0)
+                .or_insert(descriptor.relay_parent);
         }
-        let csv_file = format!("out/{up_to_block}-{name}-{para_id}.csv");
-        let mut wrt = csv::Writer::from_path(&csv_file)?;
-        for p in data.iter().copied() {
-            wrt.serialize(p)?;
+        codes
+    } else {
+        // the code is kept in storage as long as it's referenced
+        let head = subxt::finalized_head_hash(rpc_url.clone()).await?;
+        hashes.into_iter().map(|hash| (hash, head)).collect()
+    };
+
+    let host =
+        candidate_validation::start_host(pvfs_path.join("compiled"), NODE_VERSION.into()).await?;
+    let log = Diagnostics::default();
+    let total = codes.len();
+    let mut failed = 0;
+    {
+        let mut handle = host.handle().clone();
+        for (i, (code_hash, at)) in codes.into_iter().enumerate() {
+            println!("[{}/{total}] Preparing {code_hash:?}", i + 1);
+            let code_hash = ValidationCodeHash(code_hash);
+            let pvf = subxt::validation_code_by_hash(
+                &pvfs_path,
+                rpc_url.clone(),
+                code_hash,
+                at,
+                no_cache,
+            )
+            .await;
+            let result = match pvf {
+                Ok(pvf) => candidate_validation::prepare_pvf(&mut handle, &pvf, false, &log)
+                    .await
+                    .map(drop),
+                Err(e) => Err(e),
+            };
+            report::add_processed(1);
+            if let Err(e) = result {
+                eprintln!("Failed to prepare {:?}: {e:#}", code_hash.0);
+                failed += 1;
+            }
         }
-        wrt.flush()?;
-        eprintln!("Saved the data to {csv_file}");
+    }
+    // the handle is dropped, so the host can stop
+    host.shutdown().await;
+
+    println!(
+        "Prepared {} out of {total} validation codes",
+        total - failed
+    );
+    if failed > 0 {
+        anyhow::bail!("{failed} validation codes failed to prepare");
     }
     Ok(())
 }
 
-async fn handle_disputes(
-    network: String,
-    num_events: usize,
-    up_to_block: u32,
-    rpc_url: String,
+async fn handle_diff_pvf(
+    a: PvfSource,
+    b: PvfSource,
+    rpc_url: Option<String>,
+    cache: Option<PathBuf>,
+    no_cache: bool,
 ) -> anyhow::Result<()> {
-    let events = subscan::fetch_disputes_events(&network, up_to_block, num_events).await?;
-    let initiators = subscan::fetch_dispute_initiators(&network, events).await?;
-    let input = initiators.iter().map(|i| {
-        (
-            i.session_index.clone(),
-            FromStr::from_str(&i.block_hash).expect("valid block_hash"),
-        )
-    });
-    let account_map = subxt::historical_account_keys(rpc_url, input).await?;
+    let pvfs_path = cache
+        .unwrap_or_else(|| PathBuf::from(".cache"))
+        .join("pvfs");
+    std::fs::create_dir_all(&pvfs_path)?;
 
-    let initiators = initiators.into_iter().map(|i| DisputeInitiator {
-        session_index: i.session_index,
-        // TODO: handle missing keys
-        account_id: account_map[&i.session_index][i.validator_index as usize].clone(),
-    });
+    let mut raw = Vec::new();
+    for (name, source) in [("a", &a), ("b", &b)] {
+        let pvf = load_pvf(source, &pvfs_path, rpc_url.as_deref(), no_cache).await?;
+        let code = candidate_validation::decompress_pvf(&pvf)?;
+        println!(
+            "{name}: code hash {:?}, {} bytes compressed, {} bytes decompressed",
+            subxt::hash(&pvf.0),
+            pvf.0.len(),
+            code.len()
+        );
+        raw.push(code);
+    }
 
-    std::fs::create_dir_all("out")?;
+    let (a, b) = (&raw[0], &raw[1]);
+    if a == b {
+        println!("identical");
+        return Ok(());
+    }
+    let offset = a
+        .iter()
+        .zip(b.iter())
+        .position(|(x, y)| x != y)
+        .unwrap_or_else(|| a.len().min(b.len()));
+    println!("different, first difference at offset {offset:#x} of the decompressed code");
+    Ok(())
+}
+
+/// Prints the outcome of a `doctor` or `self-test` check, returns whether it passed.
+fn report_check(name: &str, started: Instant, result: anyhow::Result<String>) -> bool {
+    let elapsed = started.elapsed().as_millis();
+    match result {
+        Ok(details) => {
+            println!("{name}: OK ({elapsed}ms){details}");
+            true
+        }
+        Err(e) => {
+            println!("{name}: ERROR ({elapsed}ms): {e:#}");
+            false
+        }
+    }
+}
+
+async fn handle_doctor(network: String, rpc_url: Option<String>) -> anyhow::Result<()> {
+    let mut failed = 0;
+
+    let started = Instant::now();
+    let result = subscan::ping(&network).await.map(|()| String::new());
+    failed += !report_check("subscan", started, result) as usize;
+
+    let started = Instant::now();
+    let result = povs_today::ping(&network).await.map(|()| String::new());
+    failed += !report_check("povs.today", started, result) as usize;
+
+    match rpc_url {
+        Some(rpc_url) => {
+            let started = Instant::now();
+            let result = subxt::finalized_head_number(rpc_url)
+                .await
+                .map(|head| format!(", finalized head {head}"));
+            failed += !report_check("rpc", started, result) as usize;
+        }
+        None => println!("rpc: skipped, no --rpc-url"),
+    }
 
-    let csv_file = format!("out/disputes-{network}-{up_to_block}.csv");
-    let mut wrt = csv::Writer::from_path(&csv_file)?;
-    for i in initiators.into_iter() {
-        wrt.serialize(i)?;
+    if failed > 0 {
+        anyhow::bail!("{failed} checks failed");
     }
-    wrt.flush()?;
-    eprintln!("Saved the data to {csv_file}");
     Ok(())
 }
 
-async fn handle_validate_candidate(
+/// Candidates that are known to be valid, used by `self-test`.
+const SELF_TEST_CANDIDATES: &[(&str, &str)] = &[(
+    "kusama",
+    "0x03134f027883df8db3ce71602412d906024c96eaef06cda403c48cfb6661e5a8",
+)];
+
+async fn handle_self_test(network: String, rpc_url: String, no_cache: bool) -> anyhow::Result<()> {
+    let (_, candidate_hash) = SELF_TEST_CANDIDATES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(&network))
+        .with_context(|| format!("no known-good candidate for {network}"))?;
+    let cmd = ValidateCandidateCommand::try_parse_from([
+        "self-test",
+        "--network",
+        network.as_str(),
+        "--rpc-url",
+        rpc_url.as_str(),
+        "--candidate-hash",
+        candidate_hash,
+    ])?;
+
+    let started = Instant::now();
+    let result = handle_validate_candidate(cmd, no_cache).await;
+    if !report_check("self-test", started, result.map(|()| String::new())) {
+        anyhow::bail!("the known-good candidate {candidate_hash} failed validation");
+    }
+    Ok(())
+}
+
+async fn handle_validate_at(
     network: String,
+    para_id: u32,
+    up_to_block: Option<u32>,
     rpc_url: String,
-    candidate_hash: H256,
     cache: Option<PathBuf>,
+    no_cache: bool,
+) -> anyhow::Result<()> {
+    let up_to_block = resolve_up_to_block(up_to_block, Some(rpc_url.clone())).await?;
+    let limits = subscan::ScanLimits {
+        enough_events: 1,
+        complete_blocks: false,
+        max_blocks: None,
+    };
+    let events = subscan::fetch_inclusion_events(
+        &network,
+        up_to_block,
+        Some(para_id),
+        &[EventId::CandidateIncluded],
+        &limits,
+        false,
+    )
+    .await?;
+    let event = events
+        .iter()
+        .filter(|e| e.event_id == EventId::CandidateIncluded && e.para_id == para_id)
+        .max_by_key(|e| e.block_num)
+        .with_context(|| format!("no candidate of {para_id} included up to block {up_to_block}"))?;
+    let candidate_hash = subscan::candidate_hash_from_params(&event.params)?;
+    println!(
+        "Candidate of {para_id} included at {}: {candidate_hash:?}",
+        event.block_num
+    );
+
+    let candidate_hash = format!("{candidate_hash:?}");
+    let mut args = vec![
+        "validate-at",
+        "--network",
+        network.as_str(),
+        "--rpc-url",
+        rpc_url.as_str(),
+        "--candidate-hash",
+        candidate_hash.as_str(),
+    ];
+    let cache = cache.map(|c| c.to_string_lossy().into_owned());
+    if let Some(cache) = &cache {
+        args.extend(["--cache", cache.as_str()]);
+    }
+    let cmd = ValidateCandidateCommand::try_parse_from(args)?;
+    handle_validate_candidate(cmd, no_cache).await
+}
+
+async fn handle_validate_candidate(
+    cmd: ValidateCandidateCommand,
+    no_cache: bool,
 ) -> anyhow::Result<()> {
     let default_cache = PathBuf::from(".cache");
-    let cache = cache.unwrap_or(default_cache);
+    let cache = cmd.cache.clone().unwrap_or(default_cache);
     let _ = std::fs::create_dir_all(cache.as_path());
 
     let povs_path = cache.as_path().join("povs");
@@ -256,48 +2106,520 @@ async fn handle_validate_candidate(
     let pvfs_path = cache.as_path().join("pvfs");
     let _ = std::fs::create_dir_all(&pvfs_path);
 
-    let (pov, receipt) =
-        povs_today::get_or_fetch_candidate(povs_path, &candidate_hash, &network).await?;
+    if let Some(max) = cmd.max_download_bytes {
+        report::set_max_download_bytes(max);
+    }
+    povs_today::set_pov_cache_entries(cmd.pov_cache_entries);
+
+    if cmd.verbose_errors {
+        // inherited by the PVF workers too
+        std::env::set_var("RUST_BACKTRACE", "1");
+    }
+
+    if let Some(log_dir) = &cmd.log_dir {
+        std::fs::create_dir_all(log_dir)?;
+    }
+
+    let candidate_hashes = match (
+        cmd.candidate_hash,
+        cmd.candidates_file.as_ref(),
+        cmd.at_block,
+    ) {
+        (Some(candidate_hash), _, _) => vec![candidate_hash],
+        (None, Some(file), _) => {
+            let mut hashes = candidates::read_candidates(file)?;
+            if cmd.shuffle {
+                candidates::shuffle(&mut hashes, cmd.seed);
+            }
+            if let Some(limit) = cmd.limit {
+                hashes.truncate(limit);
+            }
+            hashes
+        }
+        (None, None, Some(at_block)) => {
+            let para_id = cmd
+                .para_id
+                .context("--para-id is required with --at-block")?;
+            let candidate_hash =
+                subscan::fetch_included_candidate(&cmd.network, at_block, para_id).await?;
+            println!("Candidate of {para_id} included at {at_block}: {candidate_hash:?}");
+            vec![candidate_hash]
+        }
+        (None, None, None) => {
+            anyhow::bail!("either --candidate-hash, --candidates-file or --at-block is required")
+        }
+    };
+
+    let host =
+        candidate_validation::start_host(pvfs_path.join("compiled"), NODE_VERSION.into()).await?;
+
+    // also on failure, so that no worker processes are left behind
+    if candidate_hashes.len() == 1 {
+        let candidate_hash = &candidate_hashes[0];
+        let result = validate_one(
+            &cmd,
+            candidate_hash,
+            &povs_path,
+            &pvfs_path,
+            no_cache,
+            host.handle(),
+        )
+        .await;
+        host.shutdown().await;
+        result?;
+        report::add_processed(1);
+        return Ok(());
+    }
+
+    let total = candidate_hashes.len();
+    let semaphore = tokio::sync::Semaphore::new(cmd.max_concurrent_validations as usize);
+    let validations = candidate_hashes
+        .iter()
+        .enumerate()
+        .map(|(i, candidate_hash)| {
+            let (cmd, semaphore, host) = (&cmd, &semaphore, host.handle());
+            let (povs_path, pvfs_path) = (&povs_path, &pvfs_path);
+            async move {
+                let _permit = semaphore.acquire().await.expect("never closed; qed");
+                // the remaining candidates would fail the same way
+                if report::download_budget_exceeded() {
+                    return false;
+                }
+                println!("[{}/{total}] Validating {candidate_hash:?}", i + 1);
+                let result =
+                    validate_one(cmd, candidate_hash, povs_path, pvfs_path, no_cache, host).await;
+                report::add_processed(1);
+                if let Err(e) = &result {
+                    eprintln!("Failed to validate {candidate_hash:?}: {e:?}");
+                }
+                result.is_ok()
+            }
+        });
+    let results = futures::future::join_all(validations).await;
+    host.shutdown().await;
+
+    // in the order of the candidates, not of completion
+    let mut summaries = report::validation_summaries();
+    summaries.sort_by_key(|s| candidate_hashes.iter().position(|c| *c == s.candidate_hash));
+    candidate_validation::print_summaries(&summaries);
+    if let Some(path) = &cmd.results_out {
+        if path.extension().map_or(false, |ext| ext == "json") {
+            let file = std::fs::File::create(path)
+                .with_context(|| format!("failed to create {}", path.display()))?;
+            output::write_json(file, &summaries, false)?;
+            report::add_output(path);
+        } else {
+            let path = path.to_string_lossy();
+            output::write_csv(&path, output::Compression::None, false, &summaries)?;
+        }
+    }
+
+    if report::download_budget_exceeded() {
+        anyhow::bail!(
+            "downloaded {} bytes, exceeding the --max-download-bytes budget",
+            report::downloaded_bytes()
+        );
+    }
+    let failed = results.iter().filter(|ok| !**ok).count();
+    println!("Validated {} out of {total} candidates", total - failed);
+    if failed > 0 {
+        anyhow::bail!("{failed} candidates failed validation");
+    }
+    Ok(())
+}
+
+fn decode_file<T: parity_scale_codec::Decode>(path: &Path) -> anyhow::Result<T> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let decoded = parity_scale_codec::decode_from_bytes(bytes.into())
+        .with_context(|| format!("failed to decode {}", path.display()))?;
+    Ok(decoded)
+}
+
+async fn validate_one(
+    cmd: &ValidateCandidateCommand,
+    candidate_hash: &H256,
+    povs_path: &Path,
+    pvfs_path: &Path,
+    no_cache: bool,
+    host: &ValidationHost,
+) -> anyhow::Result<()> {
+    let log_file = match (&cmd.log_file, &cmd.log_dir) {
+        (Some(log_file), _) => Some(log_file.clone()),
+        (None, Some(log_dir)) => Some(log_dir.join(format!("{candidate_hash:?}.log"))),
+        (None, None) => None,
+    };
+    let log = Diagnostics::new(log_file.as_deref())?;
+    log.line(format!("Candidate: {candidate_hash:?}"));
+
+    let result = validate_logged(
+        cmd,
+        candidate_hash,
+        povs_path,
+        pvfs_path,
+        no_cache,
+        host,
+        &log,
+    )
+    .await;
+    let figures = log.figures();
+    report::add_validation_summary(candidate_validation::ValidationSummary {
+        candidate_hash: *candidate_hash,
+        para_id: figures.para_id,
+        pov_size: figures.pov_size,
+        preparation_ms: figures.preparation_ms,
+        execution_ms: figures.execution_ms,
+        passed: result.is_ok(),
+        error: result.as_ref().err().map(|e| format!("{e:#}")),
+    });
+    match &result {
+        Ok(()) => log.line("Result: ok"),
+        Err(e) if cmd.verbose_errors => {
+            log.line(format!("Result: failed: {e:?}"));
+            report::inc_failed_validations();
+        }
+        Err(e) => {
+            log.line(format!("Result: failed: {e:#}"));
+            report::inc_failed_validations();
+        }
+    }
+    result
+}
+
+/// Fetches the PoV, receipt and validation code of the candidate, or reads them from the files.
+async fn fetch_inputs(
+    cmd: &ValidateCandidateCommand,
+    candidate_hash: &H256,
+    povs_path: &Path,
+    pvfs_path: &Path,
+    no_cache: bool,
+    log: &Diagnostics,
+) -> anyhow::Result<(AvailableData, CandidateReceipt<H256>, ValidationCode)> {
+    let (pov, receipt) = match (&cmd.pov_file, &cmd.receipt_file) {
+        (Some(pov_file), Some(receipt_file)) => (
+            decode_file(pov_file)?,
+            decode_file::<CandidateReceipt<H256>>(receipt_file)?,
+        ),
+        _ => {
+            povs_today::get_or_fetch_candidate(
+                povs_path.to_path_buf(),
+                candidate_hash,
+                &cmd.network,
+                cmd.max_retries,
+                no_cache,
+            )
+            .await?
+        }
+    };
+
+    if cmd.show_receipt {
+        povs_today::print_receipt(&receipt);
+    }
+
+    let pov = match &cmd.pov_from {
+        Some(pov_from) => {
+            let (other_pov, other_receipt) = povs_today::get_or_fetch_candidate(
+                povs_path.to_path_buf(),
+                pov_from,
+                &cmd.network,
+                cmd.max_retries,
+                no_cache,
+            )
+            .await?;
+            log.line(format!(
+                "Cross-validation: PoV of {pov_from:?} against the validation code of {candidate_hash:?}"
+            ));
+            let (ours, theirs) = (&receipt.descriptor, &other_receipt.descriptor);
+            if ours.para_id != theirs.para_id {
+                eprintln!(
+                    "warning: the PoV is of para_id {}, the validation code of para_id {}",
+                    theirs.para_id.0, ours.para_id.0
+                );
+            }
+            if ours.validation_code_hash != theirs.validation_code_hash {
+                eprintln!(
+                    "warning: {pov_from:?} was backed with a different validation code {:?}",
+                    theirs.validation_code_hash.0
+                );
+            }
+            other_pov
+        }
+        None => pov,
+    };
 
     let code_hash = receipt.descriptor.validation_code_hash;
     let relay_parent = receipt.descriptor.relay_parent;
 
-    let pvf = subxt::validation_code_by_hash(pvfs_path.as_path(), rpc_url, code_hash, relay_parent)
+    let pvf = match &cmd.pvf_file {
+        Some(pvf_file) => decode_file(pvf_file)?,
+        None => {
+            let rpc_url = cmd
+                .rpc_url
+                .clone()
+                .context("--rpc-url is required unless --pvf-file is set")?;
+            subxt::validation_code_by_hash(pvfs_path, rpc_url, code_hash, relay_parent, no_cache)
+                .await?
+        }
+    };
+
+    Ok((pov, receipt, pvf))
+}
+
+/// Executes the PoV against each `--extra-pvf`, reporting the outcome under the code hash.
+///
+/// A candidate failing with an extra PVF is reported, not returned as an error.
+async fn validate_extra_pvfs(
+    cmd: &ValidateCandidateCommand,
+    candidate_hash: &H256,
+    pov: &AvailableData,
+    expected_commitments: H256,
+    options: &candidate_validation::ValidationOptions,
+    host: &ValidationHost,
+    log: &Diagnostics,
+) -> anyhow::Result<()> {
+    for path in &cmd.extra_pvf {
+        let pvf: ValidationCode = decode_file(path)?;
+        let code_hash = subxt::hash(&pvf.0);
+        log.line(format!("Extra PVF {code_hash:?} from {}", path.display()));
+        let result =
+            candidate_validation::validate_candidate(host.clone(), pov.clone(), pvf, options, log)
+                .await;
+        match result {
+            Ok(Some(result)) => {
+                log.line(format!("Extra PVF {code_hash:?}: valid"));
+                candidate_validation::ValidationOutcome::new(
+                    *candidate_hash,
+                    &result,
+                    expected_commitments,
+                )
+                .print(log);
+            }
+            Ok(None) => {}
+            Err(e) => log.line(format!("Extra PVF {code_hash:?}: invalid, {e:#}")),
+        }
+    }
+    Ok(())
+}
+
+async fn validate_logged(
+    cmd: &ValidateCandidateCommand,
+    candidate_hash: &H256,
+    povs_path: &Path,
+    pvfs_path: &Path,
+    no_cache: bool,
+    host: &ValidationHost,
+    log: &Diagnostics,
+) -> anyhow::Result<()> {
+    // the already fetched inputs are cached, so a retry only fetches what's missing
+    let mut backoff = std::time::Duration::from_secs(2);
+    let mut attempt = 0;
+    let (pov, receipt, pvf) = loop {
+        match fetch_inputs(cmd, candidate_hash, povs_path, pvfs_path, no_cache, log).await {
+            Ok(inputs) => break inputs,
+            Err(e) if error::is_transient(&e) && attempt < cmd.fetch_retries => {
+                attempt += 1;
+                eprintln!(
+                    "Failed to fetch {candidate_hash:?}: {e:#}, retrying in {}s ({attempt}/{})",
+                    backoff.as_secs(),
+                    cmd.fetch_retries
+                );
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+            Err(e) => return Err(e),
+        }
+    };
+
+    log.record(|f| {
+        f.para_id.get_or_insert(receipt.descriptor.para_id.0);
+    });
+
+    if cmd.verify_erasure {
+        let rpc_url = cmd
+            .rpc_url
+            .clone()
+            .context("--verify-erasure requires --rpc-url")?;
+        let relay_parent = receipt.descriptor.relay_parent;
+        let n_validators = subxt::n_validators(rpc_url, relay_parent).await?;
+        let root = candidate_validation::erasure_root(n_validators, &pov)?;
+        let expected = receipt.descriptor.erasure_root;
+        if root == expected {
+            log.line(format!(
+                "Erasure root: matches the receipt ({n_validators} validators)"
+            ));
+        } else {
+            log.line(format!(
+                "Erasure root: MISMATCH, computed {root:?} for {n_validators} validators, \
+                 the receipt has {expected:?}"
+            ));
+        }
+    }
+
+    let options = candidate_validation::ValidationOptions {
+        show_validation_data: cmd.show_validation_data,
+        runs: cmd.determinism_check.unwrap_or(1),
+        verbose_errors: cmd.verbose_errors,
+        assume_uncompressed: cmd.assume_uncompressed,
+    };
+    let extra_pov = (!cmd.extra_pvf.is_empty()).then(|| pov.clone());
+    let result =
+        candidate_validation::validate_candidate(host.clone(), pov, pvf, &options, log).await;
+    if let Some(pov) = extra_pov {
+        let expected_commitments = receipt.commitments_hash;
+        validate_extra_pvfs(
+            cmd,
+            candidate_hash,
+            &pov,
+            expected_commitments,
+            &options,
+            host,
+            log,
+        )
         .await?;
+    }
+    let result = result?;
 
-    let path = pvfs_path.as_path().join("compiled");
-    candidate_validation::validate_candidate(path, pov, pvf, NODE_VERSION.into()).await
+    if let Some(result) = result {
+        let outcome = candidate_validation::ValidationOutcome::new(
+            *candidate_hash,
+            &result,
+            receipt.commitments_hash,
+        );
+        outcome.print(log);
+        let commitments_match = outcome.commitments_match;
+        report::add_validation(outcome);
+        if let Some(path) = &cmd.save_result {
+            candidate_validation::save_result(path, &result)?;
+            report::add_output(path);
+        }
+        // the PVF accepted the PoV, but not with the outputs the candidate was backed with
+        if !commitments_match {
+            return Err(
+                anyhow::anyhow!("the commitments of the result don't match the receipt").context(
+                    error::ErrorContext::new(error::ErrorKind::Validation)
+                        .for_candidate(*candidate_hash),
+                ),
+            );
+        }
+    }
+    Ok(())
 }
 
 fn main() -> anyhow::Result<()> {
     let rt = tokio::runtime::Runtime::new()?;
     let cli = Cli::parse();
     let security_status = Default::default();
+    let started = Instant::now();
+    progress::set_quiet(cli.quiet);
+    progress::set_json(cli.progress_json);
+    progress::set_color(cli.color);
+    subscan::set_timings(cli.timings);
+    subscan::set_verbose(cli.verbose);
+    cache::set_refresh(cli.refresh_cache);
+    subscan::set_stall_threshold(cli.stall_threshold);
+    subscan::set_modules(subscan::Modules {
+        inclusion: cli.inclusion_module.clone(),
+        disputes: cli.disputes_module.clone(),
+        disputes_call: cli.disputes_call.clone(),
+    });
+    output::set_encoding(cli.output_encoding);
+    povs_today::set_prefix_len(cli.prefix_len);
+    // the workers don't make any requests
+    let is_worker = matches!(
+        cli.commands,
+        Commands::PvfPrepareWorker(_) | Commands::PvfExecuteWorker(_)
+    );
+    if !is_worker {
+        let identity = cli.tls_cert.as_deref().zip(cli.tls_key.as_deref());
+        http::init(&cli.user_agent, identity)?;
+    }
+    if let Some(metadata_file) = &cli.metadata_file {
+        subxt::load_metadata(metadata_file)?;
+    }
+    let command = if cli.report_json || cli.prometheus_out.is_some() {
+        serde_json::to_value(&cli.commands)?
+    } else {
+        serde_json::Value::Null
+    };
 
-    match cli.commands {
-        Commands::Inclusion {
+    let result = match cli.commands {
+        Commands::Inclusion(cmd) => {
+            rt.block_on(handle_inclusion(cmd, cli.compress, cli.with_names))
+        }
+        Commands::Disputes(cmd) => rt.block_on(handle_disputes(cmd, cli.compress, cli.with_names)),
+        Commands::ValidateCandidate(cmd) => {
+            rt.block_on(handle_validate_candidate(cmd, cli.no_cache))
+        }
+        Commands::Analyze { input, kind, json } => {
+            handle_analyze(input, kind, json, cli.compress, cli.pretty)
+        }
+        Commands::ExportBundle {
+            network,
+            candidate_hash,
+            cache,
+            output,
+        } => {
+            let cache = cache.unwrap_or_else(|| PathBuf::from(".cache"));
+            let output = output.unwrap_or_else(|| {
+                let _ = std::fs::create_dir_all("out");
+                PathBuf::from(format!("out/{candidate_hash:?}.tar.gz"))
+            });
+            bundle::export(&cache, &network, &candidate_hash, &output, cli.with_names)
+        }
+        Commands::CheckMetadata { rpc_url } => rt.block_on(handle_check_metadata(rpc_url)),
+        Commands::PrintSchema { kind } => output::print_schema(kind, cli.pretty),
+        Commands::Doctor { network, rpc_url } => rt.block_on(handle_doctor(network, rpc_url)),
+        Commands::ValidateAt {
             network,
             para_id,
             up_to_block,
-            num_events,
-        } => rt.block_on(handle_inclusion(network, para_id, up_to_block, num_events)),
-        Commands::Disputes {
+            rpc_url,
+            cache,
+        } => rt.block_on(handle_validate_at(
             network,
-            num_events,
+            para_id,
             up_to_block,
             rpc_url,
-        } => rt.block_on(handle_disputes(network, num_events, up_to_block, rpc_url)),
-        Commands::ValidateCandidate {
+            cache,
+            cli.no_cache,
+        )),
+        Commands::GetPvf {
+            para_id,
+            at_block,
+            rpc_url,
+            output,
+            cache,
+        } => rt.block_on(handle_get_pvf(
+            para_id,
+            at_block,
+            rpc_url,
+            output,
+            cache,
+            cli.no_cache,
+        )),
+        Commands::WarmPvfs {
             network,
+            hashes_file,
+            from_candidates,
             rpc_url,
-            candidate_hash,
             cache,
-        } => rt.block_on(handle_validate_candidate(
+        } => rt.block_on(handle_warm_pvfs(
             network,
+            hashes_file,
+            from_candidates,
             rpc_url,
-            candidate_hash,
             cache,
+            cli.no_cache,
         )),
+        Commands::DiffPvf {
+            a,
+            b,
+            rpc_url,
+            cache,
+        } => rt.block_on(handle_diff_pvf(a, b, rpc_url, cache, cli.no_cache)),
+        Commands::SelfTest { network, rpc_url } => {
+            rt.block_on(handle_self_test(network, rpc_url, cli.no_cache))
+        }
         // TODO: Build separate workers. See github.com/paritytech/pvf-checker.
         Commands::PvfPrepareWorker(params) => {
             polkadot_node_core_pvf_prepare_worker::worker_entrypoint(
@@ -319,5 +2641,137 @@ fn main() -> anyhow::Result<()> {
             );
             Ok(())
         }
+    };
+
+    let downloaded = report::downloaded_bytes();
+    if downloaded > 0 {
+        eprintln!("Downloaded {downloaded} bytes in total");
+    }
+    let (waits, waited) = report::rate_limit_waits();
+    if cli.verbose && waits > 0 {
+        eprintln!(
+            "Waited {waits} times, {}ms in total, because of rate limiting",
+            waited.as_millis()
+        );
+    }
+
+    if cli.report_json || cli.prometheus_out.is_some() {
+        let report = report::Report::collect(command, &result, started.elapsed());
+        if let Some(path) = &cli.prometheus_out {
+            std::fs::write(path, report.to_prometheus())?;
+            eprintln!("Saved the metrics to {}", path.display());
+        }
+        if cli.report_json {
+            output::write_json(std::io::stdout(), &report, cli.pretty)?;
+            println!();
+        }
+    }
+
+    if let Err(e) = &result {
+        if cli.json_errors {
+            eprintln!("{}", error::to_json(e));
+            std::process::exit(1);
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(block_num: u32, event_id: EventId) -> InclusionEvent {
+        InclusionEvent {
+            block_num,
+            para_id: 2023,
+            event_id,
+            params: String::new(),
+            author: None,
+            session_index: None,
+        }
+    }
+
+    #[test]
+    fn timeline_pairs_inclusion_and_backing_in_the_same_block() {
+        use EventId::{CandidateBacked, CandidateIncluded};
+        // sorted as fetched, i.e. backing before inclusion within a block
+        let events = vec![
+            event(1, CandidateBacked),
+            event(2, CandidateBacked),
+            event(2, CandidateIncluded),
+            event(3, CandidateBacked),
+            event(3, CandidateIncluded),
+        ];
+
+        let timeline = Timeline::new(events, false, Sides::BOTH);
+
+        let inclusion: Vec<_> = timeline
+            .inclusion_times
+            .iter()
+            .map(|p| (p.block_num, p.blocks))
+            .collect();
+        assert_eq!(inclusion, [(2, 1), (3, 1)]);
+        let backing: Vec<_> = timeline
+            .backing_times
+            .iter()
+            .map(|p| (p.block_num, p.blocks))
+            .collect();
+        assert_eq!(backing, [(2, 0), (3, 0)]);
+        // only the candidate backed at the end of the window is pending
+        let unincluded: Vec<_> = timeline
+            .unincluded
+            .iter()
+            .map(|c| c.backed_block_num)
+            .collect();
+        assert_eq!(unincluded, [3]);
+    }
+
+    #[test]
+    fn timeline_computes_only_the_wanted_side() {
+        use EventId::{CandidateBacked, CandidateIncluded};
+        let events = || {
+            vec![
+                event(1, CandidateBacked),
+                event(2, CandidateIncluded),
+                event(3, CandidateBacked),
+                event(4, CandidateIncluded),
+            ]
+        };
+
+        let included_only = Sides {
+            backing: false,
+            inclusion: true,
+        };
+        let timeline = Timeline::new(events(), false, included_only);
+        assert!(timeline.backing_times.is_empty());
+        assert_eq!(timeline.inclusion_times.len(), 2);
+
+        let backed_only = Sides {
+            backing: true,
+            inclusion: false,
+        };
+        let timeline = Timeline::new(events(), false, backed_only);
+        assert_eq!(timeline.backing_times.len(), 1);
+        assert!(timeline.inclusion_times.is_empty());
+    }
+
+    #[test]
+    fn csv_rows_keep_their_columns_without_the_optional_fields() {
+        let point = |author: Option<&str>| InclusionPlottingPoint {
+            block_num: 1,
+            blocks: 2,
+            params: None,
+            author: author.map(str::to_owned),
+            session_index: None,
+        };
+        let mut writer = csv::Writer::from_writer(Vec::new());
+        writer.serialize(point(None)).unwrap();
+        writer.serialize(point(Some("HNZata"))).unwrap();
+        let csv = String::from_utf8(writer.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            csv,
+            "block_num,blocks,params,author,session_index\n1,2,,,\n1,2,,HNZata,\n"
+        );
     }
 }
@@ -0,0 +1,75 @@
+/// Names of well-known parachains by network and para id.
+///
+/// There is no registry of names on-chain, so this is maintained by hand.
+const PARA_NAMES: &[(&str, u32, &str)] = &[
+    ("kusama", 1000, "Statemine"),
+    ("kusama", 1001, "Encointer"),
+    ("kusama", 1002, "BridgeHub"),
+    ("kusama", 2000, "Karura"),
+    ("kusama", 2001, "Bifrost"),
+    ("kusama", 2004, "Khala"),
+    ("kusama", 2007, "Shiden"),
+    ("kusama", 2023, "Moonriver"),
+    ("kusama", 2048, "Robonomics"),
+    ("kusama", 2084, "Calamari"),
+    ("kusama", 2085, "Heiko"),
+    ("kusama", 2087, "Picasso"),
+    ("kusama", 2088, "Altair"),
+    ("kusama", 2090, "Basilisk"),
+    ("kusama", 2092, "Kintsugi"),
+    ("kusama", 2095, "Quartz"),
+    ("kusama", 2101, "Zeitgeist"),
+    ("kusama", 2105, "Crab"),
+    ("kusama", 2106, "Litmus"),
+    ("kusama", 2110, "Mangata"),
+    ("kusama", 2114, "Turing"),
+    ("polkadot", 1000, "Statemint"),
+    ("polkadot", 1001, "Collectives"),
+    ("polkadot", 1002, "BridgeHub"),
+    ("polkadot", 2000, "Acala"),
+    ("polkadot", 2004, "Moonbeam"),
+    ("polkadot", 2006, "Astar"),
+    ("polkadot", 2011, "Equilibrium"),
+    ("polkadot", 2012, "Parallel"),
+    ("polkadot", 2030, "Bifrost"),
+    ("polkadot", 2031, "Centrifuge"),
+    ("polkadot", 2032, "Interlay"),
+    ("polkadot", 2034, "HydraDX"),
+    ("polkadot", 2035, "Phala"),
+    ("polkadot", 2043, "OriginTrail"),
+    ("polkadot", 2046, "Darwinia"),
+    ("polkadot", 2086, "KILT"),
+    ("polkadot", 2094, "Pendulum"),
+    ("polkadot", 2104, "Manta"),
+];
+
+/// Returns the name of the parachain if it's a known one.
+pub fn para_name(network: &str, para_id: u32) -> Option<&'static str> {
+    PARA_NAMES
+        .iter()
+        .find(|(n, id, _)| n.eq_ignore_ascii_case(network) && *id == para_id)
+        .map(|(_, _, name)| *name)
+}
+
+/// Formats the para id for printing, e.g. `2023 (Moonriver)` with `with_names`.
+pub fn display(network: &str, para_id: u32, with_names: bool) -> String {
+    match para_name(network, para_id).filter(|_| with_names) {
+        Some(name) => format!("{para_id} ({name})"),
+        None => para_id.to_string(),
+    }
+}
+
+/// Formats the para id for output filenames, e.g. `2023-moonriver` with `with_names`.
+pub fn file_label(network: &str, para_id: u32, with_names: bool) -> String {
+    match para_name(network, para_id).filter(|_| with_names) {
+        Some(name) => format!("{para_id}-{}", name.to_lowercase()),
+        None => para_id.to_string(),
+    }
+}
+
+/// The name to record in manifests and the index, if requested and known.
+pub fn manifest_name(network: &str, para_id: u32, with_names: bool) -> Option<String> {
+    para_name(network, para_id)
+        .filter(|_| with_names)
+        .map(str::to_owned)
+}
@@ -0,0 +1,407 @@
+use crate::analysis::{InclusionStats, SessionSummary};
+use crate::candidate_validation::ValidationOutcome;
+use crate::report::Report;
+use crate::{DisputeInitiator, InclusionPlottingPoint, UnincludedCandidate};
+use anyhow::Context as _;
+use base64::Engine as _;
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Compression {
+    #[default]
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    fn extension(&self) -> &'static str {
+        match self {
+            Compression::None => "",
+            Compression::Gzip => ".gz",
+            Compression::Zstd => ".zst",
+        }
+    }
+
+    /// Infers the compression of an output file from its extension.
+    fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("gz") => Compression::Gzip,
+            Some("zst") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+}
+
+/// File format of the main outputs of `inclusion` and `disputes`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Format {
+    #[default]
+    Csv,
+    /// Requires the `parquet` feature.
+    Parquet,
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+/// The run the rows of the `--sqlite` database are tagged with.
+#[cfg_attr(not(feature = "sqlite"), allow(dead_code))]
+pub struct Run<'a> {
+    pub command: &'a str,
+    pub network: &'a str,
+    pub up_to_block: u32,
+    /// The arguments of the command.
+    pub params: serde_json::Value,
+}
+
+#[cfg(feature = "sqlite")]
+pub use crate::sqlite::{
+    write_disputes as write_sqlite_disputes, write_inclusion as write_sqlite_inclusion,
+};
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_sqlite_inclusion(
+    _path: &std::path::Path,
+    _run: &Run,
+    _para_id: u32,
+    _kind: &str,
+    _rows: &[InclusionPlottingPoint],
+) -> anyhow::Result<()> {
+    anyhow::bail!("--sqlite requires building with `--features sqlite`")
+}
+
+#[cfg(not(feature = "sqlite"))]
+pub fn write_sqlite_disputes(
+    _path: &std::path::Path,
+    _run: &Run,
+    _rows: &[DisputeInitiator],
+) -> anyhow::Result<()> {
+    anyhow::bail!("--sqlite requires building with `--features sqlite`")
+}
+
+#[cfg(feature = "parquet")]
+pub use crate::columnar::write as write_parquet;
+
+#[cfg(not(feature = "parquet"))]
+pub fn write_parquet<T>(
+    _path: &str,
+    _compress: Compression,
+    _rows: &[T],
+) -> anyhow::Result<(String, usize)> {
+    anyhow::bail!("--format parquet requires building with `--features parquet`")
+}
+
+/// Creates the output file, appending the compression extension to `path`.
+///
+/// Returns the writer and the actual path of the file.
+pub fn create(path: &str, compress: Compression) -> anyhow::Result<(Box<dyn Write>, String)> {
+    let path = format!("{path}{}", compress.extension());
+    let file = std::fs::File::create(&path)?;
+    Ok((encoder(file, compress)?, path))
+}
+
+fn encoder(file: std::fs::File, compress: Compression) -> anyhow::Result<Box<dyn Write>> {
+    let writer: Box<dyn Write> = match compress {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::write::GzEncoder::new(
+            file,
+            flate2::Compression::default(),
+        )),
+        Compression::Zstd => Box::new(zstd::Encoder::new(file, 0)?.auto_finish()),
+    };
+    Ok(writer)
+}
+
+/// Reads the lines of an existing output file, if any.
+///
+/// Appending to a compressed file adds a new gzip member or zstd frame,
+/// the decoders read them all back.
+fn read_lines(path: &str, compress: Compression) -> anyhow::Result<Vec<Vec<u8>>> {
+    let file = match std::fs::File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e.into()),
+    };
+    let lines = BufReader::new(decoder(file, compress)?)
+        .split(b'\n')
+        .collect::<Result<_, _>>()?;
+    Ok(lines)
+}
+
+fn decoder(file: std::fs::File, compress: Compression) -> anyhow::Result<Box<dyn Read>> {
+    let reader: Box<dyn Read> = match compress {
+        Compression::None => Box::new(file),
+        Compression::Gzip => Box::new(flate2::read::MultiGzDecoder::new(file)),
+        Compression::Zstd => Box::new(zstd::Decoder::new(file)?),
+    };
+    Ok(reader)
+}
+
+/// Opens a previously saved output file, decompressing it according to its extension.
+pub fn open(path: &std::path::Path) -> anyhow::Result<Box<dyn Read>> {
+    let file =
+        std::fs::File::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    decoder(file, Compression::from_path(path))
+}
+
+/// How binary fields, e.g. hashes, are rendered in the outputs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Encoding {
+    /// `0x` prefixed lowercase hex.
+    #[default]
+    Hex,
+    /// Standard base64 with padding, a third shorter than hex.
+    Base64,
+}
+
+static BASE64: AtomicBool = AtomicBool::new(false);
+
+/// Sets the encoding of the binary fields, see [`serialize_bytes`].
+pub fn set_encoding(encoding: Encoding) {
+    BASE64.store(encoding == Encoding::Base64, Ordering::Relaxed);
+}
+
+/// Renders the bytes with the `--output-encoding`.
+pub fn encode_bytes(bytes: &[u8]) -> String {
+    if BASE64.load(Ordering::Relaxed) {
+        base64::engine::general_purpose::STANDARD.encode(bytes)
+    } else {
+        let mut hex = String::with_capacity(2 + bytes.len() * 2);
+        hex.push_str("0x");
+        for byte in bytes {
+            let _ = write!(hex, "{byte:02x}");
+        }
+        hex
+    }
+}
+
+/// Parses bytes rendered as hex, `0x` prefixed or not, or as base64.
+fn decode_bytes(s: &str) -> Result<Vec<u8>, String> {
+    let hex = s.strip_prefix("0x").unwrap_or(s);
+    let is_hex = hex.len() % 2 == 0 && hex.bytes().all(|b| b.is_ascii_hexdigit());
+    if is_hex {
+        let bytes = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).expect("checked to be hex; qed"))
+            .collect();
+        return Ok(bytes);
+    }
+    base64::engine::general_purpose::STANDARD
+        .decode(s)
+        .map_err(|e| format!("{s} is neither hex nor base64: {e}"))
+}
+
+/// Serializes the bytes with the `--output-encoding`, for `#[serde(serialize_with)]`.
+pub fn serialize_bytes<T: AsRef<[u8]>, S: serde::Serializer>(
+    bytes: &T,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&encode_bytes(bytes.as_ref()))
+}
+
+/// Like [`serialize_bytes`], serializing `None` as an empty csv field.
+pub fn serialize_opt_bytes<S: serde::Serializer>(
+    bytes: &Option<Vec<u8>>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match bytes {
+        Some(bytes) => serialize_bytes(bytes, serializer),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Deserializes the optional bytes of Subscan or of a previously saved output,
+/// for `#[serde(deserialize_with)]`.
+pub fn deserialize_opt_bytes<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+    let s: Option<String> = serde::Deserialize::deserialize(deserializer)?;
+    s.filter(|s| !s.is_empty())
+        .map(|s| decode_bytes(&s))
+        .transpose()
+        .map_err(serde::de::Error::custom)
+}
+
+/// Serializes the value as JSON, human-readable with `pretty`.
+pub fn write_json<T: serde::Serialize + ?Sized>(
+    writer: impl Write,
+    value: &T,
+    pretty: bool,
+) -> anyhow::Result<()> {
+    if pretty {
+        serde_json::to_writer_pretty(writer, value)?;
+    } else {
+        serde_json::to_writer(writer, value)?;
+    }
+    Ok(())
+}
+
+/// Parses `--output-name`, which must be a plain file name, not a path.
+pub fn parse_stem(stem: &str) -> Result<String, String> {
+    if stem.is_empty() {
+        return Err("the name can't be empty".to_owned());
+    }
+    if stem.contains(['/', '\\']) {
+        return Err(format!("{stem} must not contain path separators"));
+    }
+    Ok(stem.to_owned())
+}
+
+/// Writes out the records as a csv file.
+///
+/// With `append`, the records are added to the end of an existing file and the header
+/// is only written if the file is new or empty. Rows that are already in the file are
+/// written anyway, but a warning is printed, as it's likely that the runs overlapped.
+///
+/// Returns the actual path of the file and the number of rows in it.
+pub fn write_csv<T: serde::Serialize>(
+    path: &str,
+    compress: Compression,
+    append: bool,
+    records: impl IntoIterator<Item = T>,
+) -> anyhow::Result<(String, usize)> {
+    if !append {
+        let (writer, path) = create(path, compress)?;
+        let mut wrt = csv::Writer::from_writer(writer);
+        let mut rows = 0;
+        for r in records {
+            wrt.serialize(r)?;
+            rows += 1;
+        }
+        wrt.flush()?;
+        eprintln!("Saved the data to {path}");
+        crate::report::add_output(path.clone());
+        return Ok((path, rows));
+    }
+
+    let path = format!("{path}{}", compress.extension());
+    let existing = read_lines(&path, compress)?;
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let is_empty = file.metadata()?.len() == 0;
+    let mut wrt = csv::WriterBuilder::new()
+        .has_headers(is_empty)
+        .from_writer(encoder(file, compress)?);
+
+    // the header is the first line
+    let mut rows = existing.len().saturating_sub(1);
+    let existing: HashSet<Vec<u8>> = existing.into_iter().collect();
+    let mut duplicates = 0;
+    for r in records {
+        let mut line = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        line.serialize(&r)?;
+        let mut line = line.into_inner()?;
+        line.pop(); // the terminator
+        if existing.contains(&line) {
+            duplicates += 1;
+        }
+        wrt.serialize(r)?;
+        rows += 1;
+    }
+    wrt.flush()?;
+    if duplicates > 0 {
+        eprintln!("Warning: {duplicates} of the appended rows were already in {path}");
+    }
+    eprintln!("Appended the data to {path}");
+    crate::report::add_output(path.clone());
+    Ok((path, rows))
+}
+
+#[derive(Clone, Debug, serde::Serialize, serde::Deserialize, schemars::JsonSchema)]
+pub struct IndexEntry {
+    pub file: String,
+    pub kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub para_id: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub para_name: Option<String>,
+    pub rows: usize,
+    /// The first and last block of the events the file was computed from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_range: Option<[u32; 2]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<InclusionStats>,
+}
+
+/// Adds the entries to `out/index.json`, replacing the existing entries for the same files.
+pub fn update_index(entries: Vec<IndexEntry>) -> anyhow::Result<()> {
+    let path = "out/index.json";
+    let mut index: Vec<IndexEntry> = match std::fs::read(path) {
+        Ok(bytes) => serde_json::from_slice(&bytes)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    index.retain(|old| !entries.iter().any(|new| new.file == old.file));
+    index.extend(entries);
+    index.sort_by(|a, b| a.file.cmp(&b.file));
+
+    std::fs::write(path, serde_json::to_vec_pretty(&index)?)?;
+    eprintln!("Updated {path}");
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum, serde::Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SchemaKind {
+    /// A row of the backing and inclusion outputs of `inclusion`, or of `analyze --json`.
+    Inclusion,
+    /// A row of the unincluded candidates output of `inclusion`.
+    Unincluded,
+    /// A row of the `disputes` output.
+    Disputes,
+    /// A row of the `disputes --session-summary` output.
+    DisputesSummary,
+    /// A validated candidate in the `--report-json` report.
+    Validation,
+    /// The `--report-json` report.
+    Report,
+    /// `out/index.json`.
+    Index,
+}
+
+/// Prints the JSON schema of the output to stdout.
+pub fn print_schema(kind: SchemaKind, pretty: bool) -> anyhow::Result<()> {
+    let schema = match kind {
+        SchemaKind::Inclusion => schemars::schema_for!(InclusionPlottingPoint),
+        SchemaKind::Unincluded => schemars::schema_for!(UnincludedCandidate),
+        SchemaKind::Disputes => schemars::schema_for!(DisputeInitiator),
+        SchemaKind::DisputesSummary => schemars::schema_for!(SessionSummary),
+        SchemaKind::Validation => schemars::schema_for!(ValidationOutcome),
+        SchemaKind::Report => schemars::schema_for!(Report),
+        SchemaKind::Index => schemars::schema_for!(Vec<IndexEntry>),
+    };
+    write_json(std::io::stdout(), &schema, pretty)?;
+    println!();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_bytes_of_every_encoding() {
+        let bytes = vec![0xc0, 0xff, 0xee];
+        assert_eq!(decode_bytes("0xc0ffee"), Ok(bytes.clone()));
+        assert_eq!(decode_bytes("C0FFEE"), Ok(bytes.clone()));
+        assert_eq!(decode_bytes("wP/u"), Ok(bytes));
+        assert!(decode_bytes("0xc0ffe").is_err());
+    }
+}
@@ -0,0 +1,57 @@
+use crate::InclusionPlottingPoint;
+use plotters::prelude::*;
+use std::path::Path;
+
+const COLORS: [RGBColor; 3] = [BLUE, RED, GREEN];
+
+/// Renders the backing and/or inclusion times as a line chart of blocks over block number.
+pub fn inclusion(
+    path: &Path,
+    title: &str,
+    series: &[(&str, Vec<InclusionPlottingPoint>)],
+) -> anyhow::Result<()> {
+    let plot_error = |e| anyhow::anyhow!("failed to plot {}: {e:?}", path.display());
+
+    let points = series.iter().flat_map(|(_, data)| data.iter());
+    let min_block = points.clone().map(|p| p.block_num).min().unwrap_or(0);
+    let max_block = points.clone().map(|p| p.block_num).max().unwrap_or(0);
+    let max_blocks = points.map(|p| p.blocks).max().unwrap_or(0);
+
+    let root = BitMapBackend::new(path, (1280, 720)).into_drawing_area();
+    root.fill(&WHITE).map_err(plot_error)?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(10)
+        .x_label_area_size(40)
+        .y_label_area_size(50)
+        .build_cartesian_2d(min_block..max_block + 1, 0..max_blocks + 1)
+        .map_err(plot_error)?;
+    chart
+        .configure_mesh()
+        .x_desc("block number")
+        .y_desc("blocks")
+        .draw()
+        .map_err(plot_error)?;
+
+    for ((name, data), color) in series.iter().zip(COLORS) {
+        chart
+            .draw_series(LineSeries::new(
+                data.iter().map(|p| (p.block_num, p.blocks)),
+                color,
+            ))
+            .map_err(plot_error)?
+            .label(*name)
+            .legend(move |(x, y)| PathElement::new([(x, y), (x + 20, y)], color));
+    }
+    chart
+        .configure_series_labels()
+        .background_style(WHITE)
+        .border_style(BLACK)
+        .draw()
+        .map_err(plot_error)?;
+    root.present().map_err(plot_error)?;
+
+    eprintln!("Saved the plot to {}", path.display());
+    crate::report::add_output(path);
+    Ok(())
+}
@@ -1,11 +1,215 @@
+use crate::error::{ErrorContext, ErrorKind, HttpStatus};
 use crate::primitives::{AvailableData, CandidateReceipt, H256};
+use anyhow::Context as _;
 use parity_scale_codec::Encode as _;
+use reqwest::{
+    header::{RANGE, RETRY_AFTER},
+    StatusCode,
+};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
+use tokio::time::{sleep, Duration};
+
+pub const DEFAULT_MAX_RETRIES: u32 = 5;
+
+/// How many decoded PoVs to keep in memory by default, see `--pov-cache-entries`.
+pub const DEFAULT_POV_CACHE_ENTRIES: usize = 8;
+
+type Candidate = (AvailableData, CandidateReceipt<H256>);
+
+static DECODED: Mutex<crate::cache::Lru<H256, Candidate>> = Mutex::new(crate::cache::Lru::new());
+
+/// Keeps up to `entries` decoded PoVs and receipts in memory, zero disables it.
+pub fn set_pov_cache_entries(entries: usize) {
+    DECODED.lock().expect("poisoned").set_capacity(entries);
+}
+
+/// How many hex characters of the candidate hash name its folder by default, see `--prefix-len`.
+pub const DEFAULT_PREFIX_LEN: u8 = 2;
+
+static PREFIX_LEN: AtomicU8 = AtomicU8::new(DEFAULT_PREFIX_LEN);
+
+/// Sets how many hex characters after the `0x` of the candidate hash name its folder.
+pub fn set_prefix_len(len: u8) {
+    PREFIX_LEN.store(len, Ordering::Relaxed);
+}
+
+/// The folder of the `0x` prefixed candidate hash.
+fn prefix(candidate: &str) -> &str {
+    let len = PREFIX_LEN.load(Ordering::Relaxed) as usize;
+    &candidate[2..2 + len]
+}
+
+fn pov_url(network: &str, candidate: &str) -> String {
+    let prefix = prefix(candidate);
+    format!("https://pov.data.paritytech.io/{network}/{prefix}/{candidate}")
+}
+
+fn receipt_url(network: &str, candidate: &str) -> String {
+    let prefix = prefix(candidate);
+    format!("https://pov.data.paritytech.io/{network}/{prefix}/receipts/{candidate}")
+}
+
+/// Fetches the body of `url`, retrying with backoff up to `max_retries` times
+/// when povs.today is throttling us (429 or 503).
+async fn fetch_bytes(
+    client: &reqwest::Client,
+    url: &str,
+    max_retries: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let mut backoff = Duration::from_secs(1);
+    let mut retries = 0;
+    loop {
+        let res = client.get(url).send().await?;
+        crate::report::inc_http_requests();
+        let status = res.status();
+        if status.is_success() {
+            // unknown for a compressed response, which is decompressed as it's read,
+            // so only uncompressed ones are resumed
+            let expected = res.content_length();
+            let body = res.bytes().await?.to_vec();
+            crate::report::add_downloaded(body.len())?;
+            return match expected {
+                Some(expected) => resume_download(client, url, body, expected, max_retries).await,
+                None => Ok(body),
+            };
+        }
+
+        let throttled =
+            status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE;
+        if !throttled || retries >= max_retries {
+            return Err(HttpStatus {
+                status,
+                url: url.to_owned(),
+            }
+            .into());
+        }
+
+        // respect `Retry-After` if it's given in seconds
+        let wait = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(backoff);
+
+        retries += 1;
+        eprintln!(
+            "povs.today responded with {status} for {url}, retrying in {}s ({retries}/{max_retries})",
+            wait.as_secs()
+        );
+        sleep(wait).await;
+        crate::report::add_rate_limit_wait(wait);
+        backoff *= 2;
+    }
+}
+
+/// Completes a truncated download of `url` with range requests.
+async fn resume_download(
+    client: &reqwest::Client,
+    url: &str,
+    mut body: Vec<u8>,
+    expected: u64,
+    max_retries: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let mut retries = 0;
+    while (body.len() as u64) < expected {
+        let received = body.len();
+        if retries >= max_retries {
+            anyhow::bail!(
+                "incomplete download of {url}: expected {expected} bytes, received {received}"
+            );
+        }
+        retries += 1;
+        eprintln!(
+            "incomplete download of {url}: expected {expected} bytes, received {received}, resuming"
+        );
+
+        let res = client
+            .get(url)
+            .header(RANGE, format!("bytes={received}-"))
+            .send()
+            .await?;
+        crate::report::inc_http_requests();
+        let status = res.status();
+        if status != StatusCode::PARTIAL_CONTENT {
+            anyhow::bail!(
+                "incomplete download of {url}: expected {expected} bytes, received {received}, \
+                 range request responded with {status}"
+            );
+        }
+        let chunk = res.bytes().await?;
+        crate::report::add_downloaded(chunk.len())?;
+        body.extend_from_slice(&chunk);
+    }
+
+    if body.len() as u64 != expected {
+        anyhow::bail!(
+            "download of {url} exceeds the expected size: expected {expected} bytes, received {}",
+            body.len()
+        );
+    }
+    Ok(body)
+}
+
+/// Checks that povs.today is reachable and serves the given network.
+pub async fn ping(network: &str) -> anyhow::Result<()> {
+    let url = format!("https://pov.data.paritytech.io/{network}/");
+    let res = crate::http::client()
+        .head(&url)
+        .send()
+        .await
+        .with_context(|| ErrorContext::http(&url))?;
+    crate::report::inc_http_requests();
+    let status = res.status();
+    // the listing may be disabled, but the server shouldn't be failing
+    if status.is_server_error() {
+        anyhow::bail!("povs.today responded with {status} for {url}");
+    }
+    Ok(())
+}
+
+/// Fetches only the candidate receipt from povs.today, bypassing the cache.
+pub async fn fetch_receipt(
+    candidate_hash: &H256,
+    network: &str,
+    max_retries: u32,
+) -> anyhow::Result<CandidateReceipt<H256>> {
+    let receipt_url = receipt_url(network, &format!("{candidate_hash:?}"));
+    let client = crate::http::client();
+    let receipt_bytes = fetch_bytes(&client, &receipt_url, max_retries)
+        .await
+        .with_context(|| ErrorContext::http(&receipt_url).for_candidate(*candidate_hash))?;
+    let receipt = parity_scale_codec::decode_from_bytes(receipt_bytes.into())
+        .with_context(|| ErrorContext::new(ErrorKind::Decode).for_candidate(*candidate_hash))?;
+    Ok(receipt)
+}
+
+/// Prints the candidate's descriptor and commitments hash.
+pub fn print_receipt(receipt: &CandidateReceipt<H256>) {
+    let d = &receipt.descriptor;
+    println!("Para id: {}", d.para_id.0);
+    println!("Relay parent: {:?}", d.relay_parent);
+    println!("Collator: {:?}", H256::from(d.collator.0 .0));
+    println!(
+        "Persisted validation data hash: {:?}",
+        d.persisted_validation_data_hash
+    );
+    println!("PoV hash: {:?}", d.pov_hash);
+    println!("Erasure root: {:?}", d.erasure_root);
+    println!("Para head: {:?}", d.para_head);
+    println!("Validation code hash: {:?}", d.validation_code_hash.0);
+    println!("Commitments hash: {:?}", receipt.commitments_hash);
+}
 
 pub async fn get_or_fetch_candidate(
     path: PathBuf,
     candidate_hash: &H256,
     network: &str,
+    max_retries: u32,
+    no_cache: bool,
 ) -> anyhow::Result<(AvailableData, CandidateReceipt<H256>)> {
     let candidate = format!("{candidate_hash:?}");
 
@@ -14,8 +218,16 @@ pub async fn get_or_fetch_candidate(
     let _ = std::fs::create_dir_all(receipts_dir.as_path());
     let pov_cache = path.as_path().join(&candidate);
     let receipt_cache = receipts_dir.as_path().join(&candidate);
+    let _guard = crate::cache::lock(&pov_cache).await;
 
-    if receipt_cache.as_path().exists() {
+    if !no_cache {
+        if let Some(cached) = DECODED.lock().expect("poisoned").get(candidate_hash) {
+            println!("Using in-memory PoV for {candidate}");
+            return Ok(cached);
+        }
+    }
+
+    if !no_cache && crate::cache::is_cached(&receipt_cache) {
         let pov_bytes = std::fs::read(pov_cache)?;
         let receipt_bytes = std::fs::read(receipt_cache)?;
 
@@ -28,25 +240,42 @@ pub async fn get_or_fetch_candidate(
             receipt.descriptor.para_id.0
         );
 
-        return Ok((pov, receipt));
+        let entry = (pov, receipt);
+        DECODED
+            .lock()
+            .expect("poisoned")
+            .insert(*candidate_hash, entry.clone());
+        return Ok(entry);
     }
 
     // fetch available data and receipt from povs.today
     let candidate = format!("{candidate_hash:?}");
-    let prefix = &candidate[2..4];
-    let pov_url = format!("https://pov.data.paritytech.io/{network}/{prefix}/{candidate}");
-    let receipt_url =
-        format!("https://pov.data.paritytech.io/{network}/{prefix}/receipts/{candidate}");
-    let client = reqwest::Client::new();
+    let pov_url = pov_url(network, &candidate);
+    let receipt_url = receipt_url(network, &candidate);
+    let client = crate::http::client();
 
-    let pov_req = client.get(&pov_url).send().await?;
-    let pov_bytes = pov_req.bytes().await?;
+    let pov_bytes = fetch_bytes(&client, &pov_url, max_retries)
+        .await
+        .with_context(|| ErrorContext::http(&pov_url).for_candidate(*candidate_hash))?;
+    let receipt_bytes = fetch_bytes(&client, &receipt_url, max_retries)
+        .await
+        .with_context(|| ErrorContext::http(&receipt_url).for_candidate(*candidate_hash))?;
 
-    let receipt_req = client.get(&receipt_url).send().await?;
-    let receipt_bytes = receipt_req.bytes().await?;
+    let decode_error = || ErrorContext::new(ErrorKind::Decode).for_candidate(*candidate_hash);
+    let pov: AvailableData =
+        parity_scale_codec::decode_from_bytes(pov_bytes.into()).with_context(decode_error)?;
+    let receipt: CandidateReceipt<H256> =
+        parity_scale_codec::decode_from_bytes(receipt_bytes.into()).with_context(decode_error)?;
 
-    let pov: AvailableData = parity_scale_codec::decode_from_bytes(pov_bytes)?;
-    let receipt: CandidateReceipt<H256> = parity_scale_codec::decode_from_bytes(receipt_bytes)?;
+    // don't cache inconsistent data
+    let pov_hash = crate::subxt::hash(&pov.pov.encode());
+    if pov_hash != receipt.descriptor.pov_hash {
+        return Err(anyhow::anyhow!(
+            "the PoV hashes to {pov_hash:?}, but the receipt commits to {:?}",
+            receipt.descriptor.pov_hash
+        )
+        .context(ErrorContext::new(ErrorKind::Decode).for_candidate(*candidate_hash)));
+    }
 
     // store them in the cache
     println!(
@@ -54,8 +283,18 @@ pub async fn get_or_fetch_candidate(
         receipt.descriptor.para_id.0
     );
 
-    std::fs::write(pov_cache, pov.encode())?;
-    std::fs::write(receipt_cache, receipt.encode())?;
+    if !no_cache {
+        // the receipt marks the entry as complete, so it's written last
+        crate::cache::write_atomic(&pov_cache, &pov.encode())?;
+        crate::cache::write_atomic(&receipt_cache, &receipt.encode())?;
+    }
 
-    Ok((pov, receipt))
+    let entry = (pov, receipt);
+    if !no_cache {
+        DECODED
+            .lock()
+            .expect("poisoned")
+            .insert(*candidate_hash, entry.clone());
+    }
+    Ok(entry)
 }
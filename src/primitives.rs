@@ -4,6 +4,8 @@ pub use crate::subxt::polkadot::runtime_types::polkadot_parachain::primitives::{
 pub use crate::subxt::polkadot::runtime_types::polkadot_primitives::v2::CandidateReceipt;
 pub use ::subxt::utils::{AccountId32, H256};
 pub use polkadot_node_primitives::AvailableData;
-pub use polkadot_parachain_primitives::primitives::{BlockData, ValidationParams};
+pub use polkadot_parachain_primitives::primitives::{
+    BlockData, ValidationParams, ValidationResult,
+};
 pub type SessionIndex = u32;
 pub type ValidatorIndex = u32;
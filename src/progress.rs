@@ -0,0 +1,107 @@
+use indicatif::{ProgressBar, ProgressStyle};
+use std::borrow::Cow;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+static JSON: AtomicBool = AtomicBool::new(false);
+
+/// When to use colors in the terminal output.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum ColorChoice {
+    /// Only if stdout or stderr respectively is a terminal, and `NO_COLOR` isn't set.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Enables or disables the colors of the progress indicators and other styled output.
+pub fn set_color(choice: ColorChoice) {
+    let enabled = match choice {
+        // `console` detects it by default
+        ColorChoice::Auto => return,
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    console::set_colors_enabled(enabled);
+    console::set_colors_enabled_stderr(enabled);
+}
+
+/// Hides all progress indicators.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+/// Replaces the progress bars with NDJSON progress events on stderr,
+/// e.g. `{"stage":"inclusion-events","done":120,"total":500}`.
+pub fn set_json(json: bool) {
+    JSON.store(json, Ordering::Relaxed);
+}
+
+fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+fn is_json() -> bool {
+    JSON.load(Ordering::Relaxed)
+}
+
+/// A progress bar of a `stage`, hidden under `--quiet` and `--progress-json`.
+pub struct Bar {
+    pb: ProgressBar,
+    stage: &'static str,
+    total: u64,
+}
+
+impl Bar {
+    pub fn inc(&self, delta: u64) {
+        self.pb.inc(delta);
+        self.emit(false);
+    }
+
+    pub fn finish_with_message(&self, message: impl Into<Cow<'static, str>>) {
+        self.pb.finish_with_message(message);
+        self.emit(true);
+    }
+
+    fn emit(&self, finished: bool) {
+        if !is_json() {
+            return;
+        }
+        let event = serde_json::json!({
+            "stage": self.stage,
+            "done": self.pb.position(),
+            "total": self.total,
+            "finished": finished,
+        });
+        eprintln!("{event}");
+    }
+}
+
+/// A progress bar of `len` steps.
+pub fn bar(stage: &'static str, len: u64) -> Bar {
+    let pb = if is_quiet() || is_json() {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new(len)
+    };
+    Bar {
+        pb,
+        stage,
+        total: len,
+    }
+}
+
+/// A spinner showing the elapsed time, hidden under `--quiet` and `--progress-json`.
+pub fn spinner(message: &'static str) -> ProgressBar {
+    if is_quiet() || is_json() {
+        return ProgressBar::hidden();
+    }
+    let pb = ProgressBar::new_spinner().with_message(message);
+    pb.set_style(
+        ProgressStyle::with_template("{spinner} {msg} [{elapsed}]").expect("valid template; qed"),
+    );
+    pb.enable_steady_tick(Duration::from_millis(100));
+    pb
+}
@@ -0,0 +1,275 @@
+use crate::candidate_validation::{ValidationOutcome, ValidationSummary};
+use std::fmt::Write as _;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+static HTTP_REQUESTS: AtomicUsize = AtomicUsize::new(0);
+static PROCESSED: AtomicUsize = AtomicUsize::new(0);
+static SKIPPED: AtomicUsize = AtomicUsize::new(0);
+static OUTPUTS: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+static VALIDATIONS: Mutex<Vec<ValidationOutcome>> = Mutex::new(Vec::new());
+static SUMMARIES: Mutex<Vec<ValidationSummary>> = Mutex::new(Vec::new());
+static FAILED_VALIDATIONS: AtomicUsize = AtomicUsize::new(0);
+static BLOCKS_SCANNED: AtomicUsize = AtomicUsize::new(0);
+static RATE_LIMIT_WAITS: AtomicUsize = AtomicUsize::new(0);
+static RATE_LIMIT_WAIT_MS: AtomicU64 = AtomicU64::new(0);
+static BLOCK_RANGE: Mutex<Option<[u32; 2]>> = Mutex::new(None);
+static DOWNLOADED_BYTES: AtomicU64 = AtomicU64::new(0);
+/// Zero means unlimited.
+static MAX_DOWNLOAD_BYTES: AtomicU64 = AtomicU64::new(0);
+
+/// Records an HTTP request made to Subscan or povs.today.
+pub fn inc_http_requests() {
+    HTTP_REQUESTS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records the number of events or candidates processed by a command.
+pub fn add_processed(n: usize) {
+    PROCESSED.fetch_add(n, Ordering::Relaxed);
+}
+
+/// Records an event or candidate that was skipped.
+pub fn inc_skipped() {
+    SKIPPED.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Records a file written by a command.
+pub fn add_output(path: impl Into<PathBuf>) {
+    OUTPUTS.lock().expect("poisoned").push(path.into());
+}
+
+/// Records the outcome of a candidate validation.
+pub fn add_validation(outcome: ValidationOutcome) {
+    VALIDATIONS.lock().expect("poisoned").push(outcome);
+}
+
+/// Records the summary of a candidate validation, whether it passed or not.
+pub fn add_validation_summary(summary: ValidationSummary) {
+    SUMMARIES.lock().expect("poisoned").push(summary);
+}
+
+/// The summaries of the validations recorded so far.
+pub fn validation_summaries() -> Vec<ValidationSummary> {
+    SUMMARIES.lock().expect("poisoned").clone()
+}
+
+/// Records the number of relay chain blocks a scan went through.
+pub fn add_blocks_scanned(n: u32) {
+    BLOCKS_SCANNED.fetch_add(n as usize, Ordering::Relaxed);
+}
+
+/// The first and last block of the blocks, if any.
+pub fn block_range(blocks: impl IntoIterator<Item = u32>) -> Option<[u32; 2]> {
+    blocks.into_iter().fold(None, |range, block| match range {
+        Some([min, max]) => Some([min.min(block), max.max(block)]),
+        None => Some([block, block]),
+    })
+}
+
+/// Records the range of blocks covered by the fetched events, merging it with the
+/// ranges recorded before.
+pub fn add_block_range([min, max]: [u32; 2]) {
+    let mut range = BLOCK_RANGE.lock().expect("poisoned");
+    let merged = block_range(range.iter().flatten().copied().chain([min, max]));
+    *range = merged;
+}
+
+/// Records a wait after a server throttled a request, e.g. with a 429.
+pub fn add_rate_limit_wait(wait: Duration) {
+    RATE_LIMIT_WAITS.fetch_add(1, Ordering::Relaxed);
+    RATE_LIMIT_WAIT_MS.fetch_add(wait.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// How many times and how long in total the run waited because of rate limiting.
+pub fn rate_limit_waits() -> (usize, Duration) {
+    (
+        RATE_LIMIT_WAITS.load(Ordering::Relaxed),
+        Duration::from_millis(RATE_LIMIT_WAIT_MS.load(Ordering::Relaxed)),
+    )
+}
+
+/// Records a candidate that failed validation.
+pub fn inc_failed_validations() {
+    FAILED_VALIDATIONS.fetch_add(1, Ordering::Relaxed);
+}
+
+/// Limits the total size of the PoVs, receipts and validation code downloaded in a run.
+pub fn set_max_download_bytes(max: u64) {
+    MAX_DOWNLOAD_BYTES.store(max, Ordering::Relaxed);
+}
+
+/// Records downloaded bytes, failing once the `--max-download-bytes` budget is exceeded.
+pub fn add_downloaded(bytes: usize) -> anyhow::Result<()> {
+    let total = DOWNLOADED_BYTES.fetch_add(bytes as u64, Ordering::Relaxed) + bytes as u64;
+    let max = MAX_DOWNLOAD_BYTES.load(Ordering::Relaxed);
+    if max != 0 && total > max {
+        anyhow::bail!(
+            "downloaded {total} bytes, exceeding the --max-download-bytes budget of {max}"
+        );
+    }
+    Ok(())
+}
+
+/// Whether the `--max-download-bytes` budget has been exceeded.
+pub fn download_budget_exceeded() -> bool {
+    let max = MAX_DOWNLOAD_BYTES.load(Ordering::Relaxed);
+    max != 0 && downloaded_bytes() > max
+}
+
+/// Total bytes downloaded so far.
+pub fn downloaded_bytes() -> u64 {
+    DOWNLOADED_BYTES.load(Ordering::Relaxed)
+}
+
+/// A machine-readable summary of a run, printed with `--report-json`.
+#[derive(serde::Serialize, schemars::JsonSchema)]
+pub struct Report {
+    pub command: String,
+    pub params: serde_json::Value,
+    pub success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    pub processed: usize,
+    pub skipped: usize,
+    pub http_requests: usize,
+    pub blocks_scanned: usize,
+    /// The first and last block of the fetched events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_range: Option<[u32; 2]>,
+    pub rate_limit_waits: usize,
+    pub rate_limit_wait_ms: u64,
+    pub wall_time_ms: u128,
+    pub outputs: Vec<PathBuf>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub validations: Vec<ValidationOutcome>,
+    pub failed_validations: usize,
+    pub downloaded_bytes: u64,
+}
+
+impl Report {
+    /// Collects the counters recorded during the run.
+    pub fn collect(
+        command: serde_json::Value,
+        result: &anyhow::Result<()>,
+        wall_time: Duration,
+    ) -> Self {
+        // commands are serialized as `{ "name": { params } }`
+        let (command, params) = match command {
+            serde_json::Value::Object(map) if map.len() == 1 => {
+                map.into_iter().next().expect("len is 1; qed")
+            }
+            other => (String::new(), other),
+        };
+
+        Self {
+            command,
+            params,
+            success: result.is_ok(),
+            error: result.as_ref().err().map(|e| format!("{e:?}")),
+            processed: PROCESSED.load(Ordering::Relaxed),
+            skipped: SKIPPED.load(Ordering::Relaxed),
+            http_requests: HTTP_REQUESTS.load(Ordering::Relaxed),
+            blocks_scanned: BLOCKS_SCANNED.load(Ordering::Relaxed),
+            block_range: *BLOCK_RANGE.lock().expect("poisoned"),
+            rate_limit_waits: RATE_LIMIT_WAITS.load(Ordering::Relaxed),
+            rate_limit_wait_ms: RATE_LIMIT_WAIT_MS.load(Ordering::Relaxed),
+            wall_time_ms: wall_time.as_millis(),
+            outputs: OUTPUTS.lock().expect("poisoned").clone(),
+            validations: VALIDATIONS.lock().expect("poisoned").clone(),
+            failed_validations: FAILED_VALIDATIONS.load(Ordering::Relaxed),
+            downloaded_bytes: downloaded_bytes(),
+        }
+    }
+
+    /// Formats the report in the Prometheus text exposition format.
+    pub fn to_prometheus(&self) -> String {
+        let command = &self.command;
+        let metrics: [(&str, &str, &str, f64); 12] = [
+            (
+                "success",
+                "gauge",
+                "Whether the run succeeded.",
+                self.success as u8 as f64,
+            ),
+            (
+                "processed_total",
+                "counter",
+                "Events or candidates processed.",
+                self.processed as f64,
+            ),
+            (
+                "skipped_total",
+                "counter",
+                "Events or candidates skipped.",
+                self.skipped as f64,
+            ),
+            (
+                "http_requests_total",
+                "counter",
+                "HTTP requests made to Subscan and povs.today.",
+                self.http_requests as f64,
+            ),
+            (
+                "blocks_scanned_total",
+                "counter",
+                "Relay chain blocks scanned for events.",
+                self.blocks_scanned as f64,
+            ),
+            (
+                "rate_limit_waits_total",
+                "counter",
+                "Waits after a server throttled a request.",
+                self.rate_limit_waits as f64,
+            ),
+            (
+                "rate_limit_wait_seconds_total",
+                "counter",
+                "Time spent waiting because of rate limiting.",
+                self.rate_limit_wait_ms as f64 / 1000.0,
+            ),
+            (
+                "wall_time_seconds",
+                "gauge",
+                "Duration of the run.",
+                self.wall_time_ms as f64 / 1000.0,
+            ),
+            (
+                "outputs_total",
+                "counter",
+                "Files written.",
+                self.outputs.len() as f64,
+            ),
+            (
+                "validations_passed_total",
+                "counter",
+                "Candidates that passed validation.",
+                self.validations
+                    .iter()
+                    .filter(|v| v.commitments_match)
+                    .count() as f64,
+            ),
+            (
+                "validations_failed_total",
+                "counter",
+                "Candidates that failed validation.",
+                self.failed_validations as f64,
+            ),
+            (
+                "downloaded_bytes_total",
+                "counter",
+                "Bytes of PoVs, receipts and validation code downloaded.",
+                self.downloaded_bytes as f64,
+            ),
+        ];
+
+        let mut out = String::new();
+        for (name, kind, help, value) in metrics {
+            let _ = writeln!(out, "# HELP kuddelmuddel_{name} {help}");
+            let _ = writeln!(out, "# TYPE kuddelmuddel_{name} {kind}");
+            let _ = writeln!(out, "kuddelmuddel_{name}{{command=\"{command}\"}} {value}");
+        }
+        out
+    }
+}
@@ -0,0 +1,131 @@
+//! SQLite outputs, behind the `sqlite` feature.
+//!
+//! Every run appends to the same database, so the results of several windows and networks
+//! can be queried together. The rows are tagged with the id of their row in `runs`.
+
+use crate::output::Run;
+use crate::{DisputeInitiator, InclusionPlottingPoint};
+use anyhow::Context as _;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    started_at INTEGER NOT NULL,
+    command TEXT NOT NULL,
+    network TEXT NOT NULL,
+    up_to_block INTEGER NOT NULL,
+    params TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS inclusion_points (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    para_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    block_num INTEGER NOT NULL,
+    blocks INTEGER NOT NULL,
+    params TEXT,
+    author TEXT,
+    session_index INTEGER
+);
+CREATE TABLE IF NOT EXISTS dispute_initiators (
+    run_id INTEGER NOT NULL REFERENCES runs(id),
+    session_index INTEGER NOT NULL,
+    account_id TEXT NOT NULL,
+    signature TEXT
+);
+";
+
+/// The id of the `runs` row of this process, inserted by the first write.
+static RUN_ID: Mutex<Option<i64>> = Mutex::new(None);
+
+fn open(path: &Path, run: &Run) -> anyhow::Result<(Connection, i64)> {
+    let conn =
+        Connection::open(path).with_context(|| format!("failed to open {}", path.display()))?;
+    conn.execute_batch(SCHEMA)?;
+
+    let mut run_id = RUN_ID.lock().expect("poisoned");
+    let id = match *run_id {
+        Some(id) => id,
+        None => {
+            let started_at = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+            conn.execute(
+                "INSERT INTO runs (started_at, command, network, up_to_block, params)
+                 VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![
+                    started_at,
+                    run.command,
+                    run.network,
+                    run.up_to_block,
+                    run.params.to_string()
+                ],
+            )?;
+            crate::report::add_output(path);
+            *run_id.insert(conn.last_insert_rowid())
+        }
+    };
+    Ok((conn, id))
+}
+
+/// Appends the backing or inclusion (`kind`) points of `para_id` to `inclusion_points`.
+pub fn write_inclusion(
+    path: &Path,
+    run: &Run,
+    para_id: u32,
+    kind: &str,
+    rows: &[InclusionPlottingPoint],
+) -> anyhow::Result<()> {
+    let (mut conn, run_id) = open(path, run)?;
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO inclusion_points
+             (run_id, para_id, kind, block_num, blocks, params, author, session_index)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        )?;
+        for row in rows {
+            insert.execute(params![
+                run_id,
+                para_id,
+                kind,
+                row.block_num,
+                row.blocks,
+                row.params,
+                row.author,
+                row.session_index
+            ])?;
+        }
+    }
+    tx.commit()?;
+    eprintln!("Saved {} {kind} points to {}", rows.len(), path.display());
+    Ok(())
+}
+
+/// Appends the dispute initiators to `dispute_initiators`.
+pub fn write_disputes(path: &Path, run: &Run, rows: &[DisputeInitiator]) -> anyhow::Result<()> {
+    let (mut conn, run_id) = open(path, run)?;
+    let tx = conn.transaction()?;
+    {
+        let mut insert = tx.prepare(
+            "INSERT INTO dispute_initiators (run_id, session_index, account_id, signature)
+             VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for row in rows {
+            insert.execute(params![
+                run_id,
+                row.session_index,
+                row.account_id,
+                row.signature.as_deref().map(crate::output::encode_bytes)
+            ])?;
+        }
+    }
+    tx.commit()?;
+    eprintln!(
+        "Saved {} dispute initiators to {}",
+        rows.len(),
+        path.display()
+    );
+    Ok(())
+}
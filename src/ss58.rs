@@ -0,0 +1,56 @@
+use crate::primitives::AccountId32;
+use sp_core::crypto::{Ss58AddressFormat, Ss58Codec as _};
+use std::collections::BTreeMap;
+
+/// The SS58 prefix of the addresses on `network`, the generic substrate one if unknown.
+pub fn network_prefix(network: &str) -> u16 {
+    match network.to_ascii_lowercase().as_str() {
+        "polkadot" => 0,
+        "kusama" => 2,
+        _ => 42,
+    }
+}
+
+/// Parses `--ss58-prefix`, which must fit into the 14 bits of the format.
+pub fn parse_prefix(s: &str) -> Result<u16, String> {
+    let prefix: u16 = s
+        .parse()
+        .map_err(|e| format!("{s} is not a valid prefix: {e}"))?;
+    if prefix > 16383 {
+        return Err(format!("{prefix} is out of the 0..=16383 range"));
+    }
+    // reserved by the format
+    if prefix == 46 || prefix == 47 {
+        return Err(format!("{prefix} is a reserved prefix"));
+    }
+    Ok(prefix)
+}
+
+/// Encodes the accounts with a prefix, memoizing them, since the same validators recur
+/// across the blocks and the disputes of a run.
+///
+/// Meant to live for a single command, so it holds at most the validators it has seen.
+pub struct Encoder {
+    prefix: u16,
+    encoded: BTreeMap<[u8; 32], String>,
+}
+
+impl Encoder {
+    pub fn new(prefix: u16) -> Self {
+        Self {
+            prefix,
+            encoded: BTreeMap::new(),
+        }
+    }
+
+    pub fn encode(&mut self, account: &AccountId32) -> String {
+        let prefix = self.prefix;
+        self.encoded
+            .entry(account.0)
+            .or_insert_with(|| {
+                sp_core::crypto::AccountId32::from(account.0)
+                    .to_ss58check_with_version(Ss58AddressFormat::custom(prefix))
+            })
+            .clone()
+    }
+}
@@ -1,9 +1,215 @@
-use crate::primitives::{SessionIndex, ValidatorIndex};
+use crate::error::ErrorContext;
+use crate::primitives::{SessionIndex, ValidatorIndex, H256};
 use crate::InclusionEvent;
 use anyhow::Context;
-use indicatif::ProgressBar;
+use reqwest::{header::RETRY_AFTER, StatusCode};
+use std::collections::{BTreeSet, HashSet};
+use std::hash::{Hash as _, Hasher as _};
+use std::path::Path;
 use std::str::FromStr as _;
-use tokio::time::{sleep, Duration};
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::OnceLock;
+use tokio::time::{sleep, Duration, Instant};
+
+static TIMINGS: AtomicBool = AtomicBool::new(false);
+
+/// Enables logging the latency of every Subscan request.
+pub fn set_timings(enabled: bool) {
+    TIMINGS.store(enabled, Ordering::Relaxed);
+}
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Enables logging the unexpected, but not erroneous, responses.
+pub fn set_verbose(enabled: bool) {
+    VERBOSE.store(enabled, Ordering::Relaxed);
+}
+
+/// The Subscan module and call names of the events we query.
+pub struct Modules {
+    pub inclusion: String,
+    pub disputes: String,
+    pub disputes_call: String,
+}
+
+impl Default for Modules {
+    fn default() -> Self {
+        Self {
+            inclusion: "parainclusion".to_owned(),
+            disputes: "parasdisputes".to_owned(),
+            disputes_call: "disputeinitiated".to_owned(),
+        }
+    }
+}
+
+static MODULES: OnceLock<Modules> = OnceLock::new();
+
+/// Overrides the module and call names, in case Subscan renames them.
+pub fn set_modules(modules: Modules) {
+    // only set once at startup
+    let _ = MODULES.set(modules);
+}
+
+fn modules() -> &'static Modules {
+    MODULES.get_or_init(Modules::default)
+}
+
+/// How many blocks or pages in a row may bring no new events before a scan gives up.
+pub const DEFAULT_STALL_THRESHOLD: u32 = 20;
+
+static STALL_THRESHOLD: AtomicU32 = AtomicU32::new(DEFAULT_STALL_THRESHOLD);
+
+/// Sets the `--stall-threshold`, zero disables the watchdog.
+pub fn set_stall_threshold(threshold: u32) {
+    STALL_THRESHOLD.store(threshold, Ordering::Relaxed);
+}
+
+/// Stops the fetch loops when Subscan keeps returning empty or already seen events
+/// without triggering their own break conditions.
+#[derive(Default)]
+struct Watchdog {
+    idle: u32,
+}
+
+impl Watchdog {
+    /// Records an iteration, returns whether the scan has stalled.
+    fn stalled(&mut self, new_events: usize) -> bool {
+        if new_events > 0 {
+            self.idle = 0;
+            return false;
+        }
+        self.idle += 1;
+        let threshold = STALL_THRESHOLD.load(Ordering::Relaxed);
+        threshold != 0 && self.idle >= threshold
+    }
+}
+
+/// Waits between requests so that we don't trigger Subscan's rate limiting.
+///
+/// Not counted as a rate limit wait, unlike the waits after Subscan responded with a 429.
+async fn throttle() {
+    sleep(Duration::from_millis(150)).await;
+}
+
+/// How many times a request is retried when Subscan responds with a 429.
+const MAX_RATE_LIMIT_RETRIES: u32 = 5;
+
+/// Time spent waiting for Subscan, excluding the rate limiting sleeps.
+#[derive(Default)]
+struct Timings {
+    requests: usize,
+    network_time: Duration,
+}
+
+impl Timings {
+    fn record(&mut self, elapsed: Duration) {
+        self.requests += 1;
+        self.network_time += elapsed;
+        if TIMINGS.load(Ordering::Relaxed) {
+            eprintln!("request #{} took {}ms", self.requests, elapsed.as_millis());
+        }
+    }
+
+    fn print_summary(&self, what: &str) {
+        if !TIMINGS.load(Ordering::Relaxed) || self.requests == 0 {
+            return;
+        }
+        eprintln!(
+            "{what}: {} requests, {}ms total network time, {}ms average latency",
+            self.requests,
+            self.network_time.as_millis(),
+            self.network_time.as_millis() / self.requests as u128,
+        );
+    }
+}
+
+/// Sorts the events and removes the duplicates.
+///
+/// With `strict`, duplicates are an error, since they may indicate a paging bug.
+fn dedup_events<T: Ord + std::fmt::Debug>(events: &mut Vec<T>, strict: bool) -> anyhow::Result<()> {
+    events.sort();
+    if strict {
+        let duplicates: Vec<&T> = events
+            .windows(2)
+            .filter(|w| w[0] == w[1])
+            .map(|w| &w[0])
+            .collect();
+        if !duplicates.is_empty() {
+            anyhow::bail!(
+                "{} duplicate events found: {duplicates:?}",
+                duplicates.len()
+            );
+        }
+        return Ok(());
+    }
+
+    let total = events.len();
+    events.dedup();
+    if events.len() != total {
+        eprintln!("{} duplicate events found", total - events.len());
+    }
+    Ok(())
+}
+
+/// Prints and records the range of blocks covered by the fetched events.
+///
+/// The scans stop at a number of events rather than at a block, so this is
+/// the window the data actually represents.
+fn report_block_range(blocks: impl Iterator<Item = u32>) {
+    match crate::report::block_range(blocks) {
+        Some([min, max]) => {
+            eprintln!("The events cover blocks {min} to {max}");
+            crate::report::add_block_range([min, max]);
+        }
+        None => eprintln!("No events found"),
+    }
+}
+
+/// Posts the request to Subscan and decodes the JSON response.
+async fn post<T: serde::de::DeserializeOwned>(
+    url: &str,
+    request: &impl serde::Serialize,
+    timings: &mut Timings,
+    context: impl Fn() -> ErrorContext,
+) -> anyhow::Result<T> {
+    let client = crate::http::client();
+    let mut backoff = Duration::from_secs(1);
+    let mut retries = 0;
+    let (res, now) = loop {
+        let now = Instant::now();
+        let res = client
+            .post(url)
+            .json(request)
+            .send()
+            .await
+            .with_context(&context)?;
+        crate::report::inc_http_requests();
+        if res.status() != StatusCode::TOO_MANY_REQUESTS || retries >= MAX_RATE_LIMIT_RETRIES {
+            break (res, now);
+        }
+
+        // respect `Retry-After` if it's given in seconds
+        let wait = res
+            .headers()
+            .get(RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs)
+            .unwrap_or(backoff);
+        retries += 1;
+        eprintln!(
+            "Subscan is rate limiting {url}, retrying in {}s ({retries}/{MAX_RATE_LIMIT_RETRIES})",
+            wait.as_secs()
+        );
+        sleep(wait).await;
+        crate::report::add_rate_limit_wait(wait);
+        backoff *= 2;
+    };
+
+    let response = res.json::<T>().await.with_context(&context)?;
+    timings.record(now.elapsed());
+    Ok(response)
+}
 
 pub mod events {
     use serde::{Deserialize, Serialize};
@@ -34,10 +240,23 @@ pub mod events {
             pub events: Option<Vec<Event>>,
         }
 
-        #[derive(Debug, Deserialize)]
+        /// Ordered so that, within a block, backing sorts before inclusion.
+        #[derive(
+            Clone,
+            Copy,
+            Debug,
+            PartialEq,
+            Eq,
+            PartialOrd,
+            Ord,
+            Deserialize,
+            Serialize,
+            clap::ValueEnum,
+        )]
+        #[clap(rename_all = "PascalCase")]
         pub enum EventId {
-            CandidateIncluded,
             CandidateBacked,
+            CandidateIncluded,
             CandidateTimedOut,
         }
 
@@ -65,7 +284,9 @@ pub mod events {
         #[derive(Debug, Deserialize, PartialOrd, PartialEq, Eq, Ord)]
         pub struct Event {
             pub block_num: u32,
-            pub extrinsic_idx: u32,
+            /// Missing for events not emitted by an extrinsic.
+            #[serde(default)]
+            pub extrinsic_idx: Option<u32>,
         }
     }
 }
@@ -108,6 +329,8 @@ pub mod extrinsic {
 
         #[derive(Debug, Deserialize)]
         pub struct DisputeVotes {
+            #[serde(default)]
+            pub candidate_hash: Option<String>,
             pub session: SessionIndex,
             pub statements: Vec<DisputeVote>,
         }
@@ -118,6 +341,13 @@ pub mod extrinsic {
             pub kind: HashMap<DisputeVoteKind, serde_json::Value>,
             #[serde(rename = "col2")]
             pub validator_index: ValidatorIndex,
+            /// The validator's signature of the statement.
+            #[serde(
+                rename = "col3",
+                default,
+                deserialize_with = "crate::output::deserialize_opt_bytes"
+            )]
+            pub signature: Option<Vec<u8>>,
         }
 
         #[derive(Debug, Deserialize, Eq, PartialEq, Hash)]
@@ -132,124 +362,365 @@ impl TryFrom<events::inclusion::Event> for InclusionEvent {
     type Error = ();
 
     fn try_from(event: events::inclusion::Event) -> Result<Self, Self::Error> {
-        use events::inclusion::EventId::*;
-
         let substr = "\"para_id\":";
         let idx = event.params.find(substr).ok_or(())? + substr.len();
         let para_id = u32::from_str(&event.params[idx..idx + 4]).map_err(|_| ())?;
-        let block_num = event.block_num;
-
-        let included = match event.event_id {
-            CandidateIncluded => true,
-            CandidateBacked => false,
-            _ => {
-                eprintln!("{block_num}: skipping CandidateTimedOut({para_id})");
-                return Err(());
-            }
-        };
 
         Ok(Self {
-            block_num,
+            block_num: event.block_num,
             para_id,
-            included,
+            event_id: event.event_id,
+            params: event.params,
+            author: None,
+            session_index: None,
         })
     }
 }
 
+/// Makes the smallest possible events request to check that Subscan is reachable.
+pub async fn ping(network: &str) -> anyhow::Result<()> {
+    let url = format!("https://{network}.api.subscan.io/api/scan/events");
+    let request = events::Request {
+        row: 1,
+        page: 0,
+        module: &modules().inclusion,
+        block_num: None,
+        call: None,
+        block_range: None,
+    };
+    let mut timings = Timings::default();
+    let _: events::inclusion::Response =
+        post(&url, &request, &mut timings, || ErrorContext::http(&url)).await?;
+    Ok(())
+}
+
+/// When to stop scanning for inclusion events.
+pub struct ScanLimits {
+    pub enough_events: usize,
+    /// All the pages of events of each block are fetched, but the scan stops as soon as
+    /// `enough_events` is reached, which may be in the middle of the last block. With
+    /// `complete_blocks`, the remaining pages of the last block are fetched too, so the
+    /// result may contain more than `enough_events` events, but never a partial block.
+    pub complete_blocks: bool,
+    /// Stop after this many blocks even if there are not enough events.
+    pub max_blocks: Option<u32>,
+}
+
+/// Fetches the inclusion events for `para_id` block by block, going back from `up_to_block`.
+/// Without `para_id`, the events of all parachains are kept.
+/// Only the events of the `selected` kinds are kept.
 pub async fn fetch_inclusion_events(
     network: &str,
     up_to_block: u32,
-    para_id: u32,
-    enough_events: usize,
+    para_id: Option<u32>,
+    selected: &[events::inclusion::EventId],
+    limits: &ScanLimits,
+    strict_dedup: bool,
 ) -> anyhow::Result<Vec<InclusionEvent>> {
+    const ROW: u32 = 100;
+    let ScanLimits {
+        enough_events,
+        complete_blocks,
+        max_blocks,
+    } = *limits;
+
     let url = format!("https://{network}.api.subscan.io/api/scan/events");
     let mut events: Vec<InclusionEvent> = Vec::new();
-    eprintln!("Fetching {enough_events} events for {network}, para_id({para_id}) up to block {up_to_block}");
-    let pb = ProgressBar::new(enough_events as u64);
+    let paras = match para_id {
+        Some(para_id) => format!("para_id({para_id})"),
+        None => "all parachains".to_owned(),
+    };
+    eprintln!("Fetching {enough_events} events for {network}, {paras} up to block {up_to_block}");
+    let pb = crate::progress::bar("inclusion-events", enough_events as u64);
+    let mut timings = Timings::default();
     let mut block_num = up_to_block;
+    let mut blocks_scanned = 0;
+    // the params are only hashed, we don't need to keep the events of the other parachains
+    let mut seen: HashSet<(u32, u64)> = HashSet::new();
+    let mut watchdog = Watchdog::default();
     while events.len() < enough_events {
+        if max_blocks.map_or(false, |max| blocks_scanned >= max) {
+            eprintln!(
+                "Stopping after {blocks_scanned} blocks with {} out of {enough_events} events",
+                events.len()
+            );
+            break;
+        }
+        let mut page = 0;
+        let mut unique_in_block = 0;
+        loop {
+            let request = events::Request {
+                row: ROW,
+                page,
+                module: &modules().inclusion,
+                block_num: Some(block_num),
+                call: None,
+                block_range: None,
+            };
+            let response: events::inclusion::Response = post(&url, &request, &mut timings, || {
+                ErrorContext::http(&url).at_block(block_num)
+            })
+            .await?;
+            let raw_events: Vec<events::inclusion::Event> = response
+                .data
+                .into_iter()
+                .flat_map(|d| d.events)
+                .flatten()
+                .collect();
+            if blocks_scanned == 0 && page == 0 && raw_events.is_empty() {
+                eprintln!(
+                    "Warning: no events at block {block_num}, \
+                     check that --up-to-block isn't in the future"
+                );
+            }
+            let full_page = raw_events.len() == ROW as usize;
+            unique_in_block += raw_events
+                .iter()
+                .filter(|e| {
+                    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+                    e.params.hash(&mut hasher);
+                    seen.insert((e.block_num, hasher.finish()))
+                })
+                .count();
+            let new_events: Vec<InclusionEvent> = raw_events
+                .into_iter()
+                .flat_map(|e| InclusionEvent::try_from(e).ok())
+                .filter(|e| para_id.map_or(true, |p| e.para_id == p))
+                .filter(|e| selected.contains(&e.event_id))
+                .collect();
+
+            pb.inc(new_events.len() as u64);
+            events.extend(new_events);
+            throttle().await;
+
+            let reached = events.len() >= enough_events;
+            if !full_page || (reached && !complete_blocks) {
+                break;
+            }
+            page += 1;
+        }
+        block_num -= 1;
+        blocks_scanned += 1;
+        if watchdog.stalled(unique_in_block) {
+            eprintln!(
+                "Warning: no new events in the last {} blocks, stopping with {} out of \
+                 {enough_events} events",
+                watchdog.idle,
+                events.len()
+            );
+            break;
+        }
+    }
+    pb.finish_with_message("Fetching complete!");
+    timings.print_summary("inclusion events");
+    eprintln!(
+        "Scanned {blocks_scanned} blocks, from {} to {up_to_block}",
+        block_num + 1
+    );
+    crate::report::add_blocks_scanned(blocks_scanned);
+
+    dedup_events(&mut events, strict_dedup)?;
+    report_block_range(events.iter().map(|e| e.block_num));
+
+    Ok(events)
+}
+
+fn decode_hex(s: &str) -> anyhow::Result<Vec<u8>> {
+    let s = s.strip_prefix("0x").unwrap_or(s);
+    if s.len() % 2 != 0 {
+        anyhow::bail!("odd length hex string {s}");
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16).map_err(Into::into))
+        .collect()
+}
+
+/// Computes the candidate hash from the `CandidateReceipt` in the event params.
+///
+/// The candidate hash is the hash of the SCALE encoded receipt,
+/// which is just the concatenation of its fields.
+pub fn candidate_hash_from_params(params: &str) -> anyhow::Result<H256> {
+    let params: Vec<serde_json::Value> = serde_json::from_str(params)?;
+    let receipt = params
+        .iter()
+        .map(|p| &p["value"])
+        .find(|v| v.get("descriptor").is_some())
+        .context("no candidate receipt in the event params")?;
+    let descriptor = &receipt["descriptor"];
+
+    let para_id = descriptor["para_id"]
+        .as_u64()
+        .context("missing para_id in the descriptor")? as u32;
+    let mut encoded = para_id.to_le_bytes().to_vec();
+    for field in [
+        "relay_parent",
+        "collator",
+        "persisted_validation_data_hash",
+        "pov_hash",
+        "erasure_root",
+        "signature",
+        "para_head",
+        "validation_code_hash",
+    ] {
+        let hex = descriptor[field]
+            .as_str()
+            .with_context(|| format!("missing {field} in the descriptor"))?;
+        encoded.extend(decode_hex(hex)?);
+    }
+    let commitments_hash = receipt["commitments_hash"]
+        .as_str()
+        .context("missing commitments_hash in the receipt")?;
+    encoded.extend(decode_hex(commitments_hash)?);
+
+    Ok(crate::subxt::hash(&encoded))
+}
+
+/// Finds the hash of the candidate of `para_id` included at `block_num`.
+pub async fn fetch_included_candidate(
+    network: &str,
+    block_num: u32,
+    para_id: u32,
+) -> anyhow::Result<H256> {
+    const ROW: u32 = 100;
+    let url = format!("https://{network}.api.subscan.io/api/scan/events");
+    let mut timings = Timings::default();
+    // a busy block can have more inclusion events than fit in a page
+    for page in 0.. {
         let request = events::Request {
-            row: 100,
-            page: 0,
-            module: "parainclusion",
+            row: ROW,
+            page,
+            module: &modules().inclusion,
             block_num: Some(block_num),
             call: None,
             block_range: None,
         };
-        let client = reqwest::Client::new();
-        let res = client.post(&url).json(&request).send().await?;
+        let response: events::inclusion::Response = post(&url, &request, &mut timings, || {
+            ErrorContext::http(&url).at_block(block_num)
+        })
+        .await?;
 
-        let response = res.json::<events::inclusion::Response>().await?;
-        let new_events: Vec<InclusionEvent> = response
+        let page_events: Vec<_> = response
             .data
             .into_iter()
             .flat_map(|d| d.events)
             .flatten()
-            .flat_map(|e| InclusionEvent::try_from(e).ok())
-            .filter(|e| e.para_id == para_id)
             .collect();
-
-        pb.inc(new_events.len() as u64);
-        block_num -= 1;
-        events.extend(new_events);
-        // don't trigger rate limiting
-        sleep(Duration::from_millis(150)).await;
-    }
-    pb.finish_with_message("Fetching complete!");
-
-    events.reverse();
-    let total = events.len();
-    events.sort();
-    events.dedup();
-    if events.len() != total {
-        eprintln!("{} duplicate events found", total - events.len());
+        let full_page = page_events.len() == ROW as usize;
+        let event = page_events
+            .into_iter()
+            .flat_map(|e| InclusionEvent::try_from(e).ok())
+            .find(|e| {
+                e.event_id == events::inclusion::EventId::CandidateIncluded && e.para_id == para_id
+            });
+        if let Some(event) = event {
+            return candidate_hash_from_params(&event.params);
+        }
+        if !full_page {
+            break;
+        }
     }
 
-    Ok(events)
+    anyhow::bail!("no candidate of {para_id} included at block {block_num}")
 }
 
+/// Fetches the `DisputeInitiated` events page by page, starting from `start_page`.
+///
+/// Subscan sometimes returns a partial page in the middle of the range while it's still
+/// indexing. These are reported, and with `retry_partial` refetched once after a delay.
 pub async fn fetch_disputes_events(
     network: &str,
     up_to_block: u32,
     enough_events: usize,
+    start_page: u32,
+    strict_dedup: bool,
+    retry_partial: bool,
 ) -> anyhow::Result<Vec<events::disputes::Event>> {
+    const ROW: u32 = 100;
     let url = format!("https://{network}.api.subscan.io/api/scan/events");
     let mut disputes_initiated: Vec<events::disputes::Event> = Vec::new();
-    let pb = ProgressBar::new(enough_events as u64);
+    let pb = crate::progress::bar("disputes-events", enough_events as u64);
     let from_block = up_to_block.saturating_sub(1_000_000); // HACK
     let range = format!("{from_block}-{up_to_block}");
-    let mut page = 0;
+    let mut timings = Timings::default();
+    let mut page = start_page;
+    // the last page is expected to be partial, only the ones followed by more events are not
+    let mut partial_page: Option<(u32, usize)> = None;
+    let mut seen: BTreeSet<(u32, Option<u32>)> = BTreeSet::new();
+    let mut watchdog = Watchdog::default();
     while disputes_initiated.len() < enough_events {
         let request = events::Request {
-            row: 100,
+            row: ROW,
             page,
-            module: "parasdisputes",
-            call: Some("disputeinitiated"),
+            module: &modules().disputes,
+            call: Some(&modules().disputes_call),
             block_range: Some(range.clone()),
             block_num: None,
         };
-        let client = reqwest::Client::new();
-        let res = client.post(&url).json(&request).send().await?;
-
-        let response = res.json::<events::disputes::Response>().await?;
-        let new_events: Vec<events::disputes::Event> =
+        let response: events::disputes::Response =
+            post(&url, &request, &mut timings, || ErrorContext::http(&url)).await?;
+        let mut new_events: Vec<events::disputes::Event> =
             response.data.events.into_iter().flatten().collect();
 
+        if retry_partial && !new_events.is_empty() && new_events.len() < ROW as usize {
+            sleep(Duration::from_secs(2)).await;
+            let response: events::disputes::Response =
+                post(&url, &request, &mut timings, || ErrorContext::http(&url)).await?;
+            let retried: Vec<events::disputes::Event> =
+                response.data.events.into_iter().flatten().collect();
+            if retried.len() > new_events.len() {
+                eprintln!(
+                    "page {page}: got {} events on retry instead of {}",
+                    retried.len(),
+                    new_events.len()
+                );
+                new_events = retried;
+            }
+        }
+
         pb.inc(new_events.len() as u64);
-        page += 1;
 
         if new_events.is_empty() {
+            if page == 0 {
+                eprintln!(
+                    "Warning: no disputes initiated in blocks {range}, \
+                     check that --up-to-block isn't in the future"
+                );
+            }
             break;
         }
+        if let Some((partial, len)) = partial_page.take() {
+            eprintln!(
+                "page {partial}: got only {len} of {ROW} events, but more followed, \
+                 Subscan may still be indexing the range"
+            );
+        }
+        if new_events.len() < ROW as usize {
+            partial_page = Some((page, new_events.len()));
+        }
+        page += 1;
+        let unique = new_events
+            .iter()
+            .filter(|e| seen.insert((e.block_num, e.extrinsic_idx)))
+            .count();
         disputes_initiated.extend(new_events);
-        // don't trigger rate limiting
-        sleep(Duration::from_millis(150)).await;
+        if watchdog.stalled(unique) {
+            eprintln!(
+                "Warning: no new events in the last {} pages, stopping with {} out of \
+                 {enough_events} events",
+                watchdog.idle,
+                disputes_initiated.len()
+            );
+            break;
+        }
+        throttle().await;
     }
     let num_events = disputes_initiated.len();
     pb.finish_with_message(format!("Fetched {num_events} events."));
+    timings.print_summary("disputes events");
 
-    disputes_initiated.sort();
-    disputes_initiated.dedup();
+    dedup_events(&mut disputes_initiated, strict_dedup)?;
+    report_block_range(disputes_initiated.iter().map(|e| e.block_num));
 
     Ok(disputes_initiated)
 }
@@ -259,32 +730,58 @@ pub struct DisputeInitiated {
     pub session_index: SessionIndex,
     pub validator_index: ValidatorIndex,
     #[serde(skip)]
+    pub block_num: u32,
+    #[serde(skip)]
     pub block_hash: String,
+    #[serde(skip)]
+    pub candidate_hash: Option<H256>,
+    #[serde(skip)]
+    pub signature: Option<Vec<u8>>,
+    /// Whether the vote is for the candidate, as opposed to against it.
+    #[serde(skip)]
+    pub valid: bool,
 }
 
+/// Fetches the votes of the disputes initiated by `events`, both valid and invalid.
+///
+/// With `raw_output`, the responses are also written to `<block>-<extrinsic>.json` in it
+/// as they are, before parsing, so that parsing mismatches can be reported.
 pub async fn fetch_dispute_initiators(
     network: &str,
     events: Vec<events::disputes::Event>,
+    raw_output: Option<&Path>,
 ) -> anyhow::Result<Vec<DisputeInitiated>> {
     let url = format!("https://{network}.api.subscan.io/api/scan/extrinsic");
     let mut initiators = Vec::new();
-    let pb = ProgressBar::new(events.len() as u64);
+    let pb = crate::progress::bar("dispute-votes", events.len() as u64);
+    let mut timings = Timings::default();
     for event in events {
         let events::disputes::Event {
             block_num,
             extrinsic_idx,
         } = event;
+        let Some(extrinsic_idx) = extrinsic_idx else {
+            eprintln!("{block_num}: dispute event without an extrinsic index, skipping");
+            crate::report::inc_skipped();
+            pb.inc(1);
+            continue;
+        };
 
         let request = extrinsic::Request {
             extrinsic_index: format!("{block_num}-{extrinsic_idx}"),
         };
 
-        let client = reqwest::Client::new();
-        let res = client.post(&url).json(&request).send().await?;
-
-        let response = res
-            .json::<extrinsic::parainherent::Response>()
-            .await
+        let response: serde_json::Value = post(&url, &request, &mut timings, || {
+            ErrorContext::http(&url).at_block(block_num)
+        })
+        .await?;
+        if let Some(dir) = raw_output {
+            let path = dir.join(format!("{block_num}-{extrinsic_idx}.json"));
+            let json = serde_json::to_vec_pretty(&response)?;
+            std::fs::write(&path, json)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        let response: extrinsic::parainherent::Response = serde_json::from_value(response)
             .with_context(|| {
                 format!("unexpected response for parainherent {block_num}-{extrinsic_idx}")
             })?;
@@ -293,6 +790,7 @@ pub async fn fetch_dispute_initiators(
             Some(data) => data,
             None => {
                 eprintln!("null response for extrinsic {block_num}-{extrinsic_idx}, skipping",);
+                crate::report::inc_skipped();
                 continue;
             }
         };
@@ -300,24 +798,57 @@ pub async fn fetch_dispute_initiators(
         let disputes: Vec<extrinsic::parainherent::DisputeVotes> =
             data.params.remove(0).value.disputes;
 
-        if disputes.is_empty() {
-            eprintln!("got 0 disputes for extrinsic {block_num}-{extrinsic_idx}",);
+        // the extrinsic initiated a dispute, so it must have at least one vote
+        let num_votes: usize = disputes.iter().map(|d| d.statements.len()).sum();
+        if num_votes == 0 && VERBOSE.load(Ordering::Relaxed) {
+            eprintln!(
+                "got {} disputes, but no votes for extrinsic {block_num}-{extrinsic_idx}, \
+                 the response format may have changed, see --raw-output",
+                disputes.len()
+            );
         }
 
         for votes in disputes {
             let session_index = votes.session;
+            let candidate_hash = votes
+                .candidate_hash
+                .as_deref()
+                .and_then(|h| H256::from_str(h).ok());
             for vote in votes.statements {
                 let invalid = extrinsic::parainherent::DisputeVoteKind::Invalid;
-                if vote.kind.contains_key(&invalid) {
-                    initiators.push(DisputeInitiated {
-                        session_index,
-                        block_hash: block_hash.clone(),
-                        validator_index: vote.validator_index,
-                    });
-                }
+                initiators.push(DisputeInitiated {
+                    session_index,
+                    block_num,
+                    block_hash: block_hash.clone(),
+                    candidate_hash,
+                    validator_index: vote.validator_index,
+                    signature: vote.signature,
+                    valid: !vote.kind.contains_key(&invalid),
+                });
             }
         }
     }
     pb.finish_with_message("Fetching complete!");
+    timings.print_summary("dispute extrinsics");
     Ok(initiators)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disputes_event_without_extrinsic_idx() {
+        let response: events::disputes::Response = serde_json::from_str(
+            r#"{"data": {"events": [
+                {"block_num": 100, "extrinsic_idx": 1},
+                {"block_num": 101}
+            ]}}"#,
+        )
+        .unwrap();
+        let events = response.data.events.unwrap();
+        assert_eq!(events[0].extrinsic_idx, Some(1));
+        assert_eq!(events[1].block_num, 101);
+        assert_eq!(events[1].extrinsic_idx, None);
+    }
+}
@@ -1,18 +1,96 @@
 use std::collections::{btree_map::Entry, BTreeMap};
 use std::path::Path;
+use std::sync::OnceLock;
 
+use crate::error::{ErrorContext, ErrorKind};
 use crate::primitives::{SessionIndex, ValidationCode, ValidationCodeHash};
-use parity_scale_codec::Encode as _;
+use crate::subscan::DisputeInitiated;
+use anyhow::Context as _;
+use parity_scale_codec::{Decode as _, Encode as _};
+use subxt::config::substrate::DigestItem;
+use subxt::config::{Config, Hasher as _};
 use subxt::{utils::AccountId32, utils::H256, OnlineClient, PolkadotConfig};
 
 #[subxt::subxt(runtime_metadata_path = "assets/kusama_metadata.scale")]
 pub mod polkadot {}
 
+static METADATA: OnceLock<subxt::Metadata> = OnceLock::new();
+
+/// Makes the clients use the SCALE encoded metadata in this file instead of the metadata
+/// of the node's current runtime, e.g. to query blocks from before a runtime upgrade.
+pub fn load_metadata(path: &Path) -> anyhow::Result<()> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("failed to read {}", path.display()))?;
+    let prefixed = subxt::ext::frame_metadata::RuntimeMetadataPrefixed::decode(&mut &bytes[..])
+        .with_context(|| format!("failed to decode the metadata in {}", path.display()))?;
+    let metadata = subxt::Metadata::try_from(prefixed)
+        .with_context(|| format!("unsupported metadata in {}", path.display()))?;
+    METADATA
+        .set(metadata)
+        .map_err(|_| anyhow::anyhow!("the metadata is already loaded"))
+}
+
+/// The static storage address, without the validation against the metadata if it's loaded
+/// with `--metadata-file`: its hashes differ from the bundled ones the addresses were generated
+/// with, even when the queried items haven't changed.
+macro_rules! address {
+    ($address:expr) => {{
+        let address = $address;
+        if METADATA.get().is_some() {
+            address.unvalidated()
+        } else {
+            address
+        }
+    }};
+}
+
+async fn connect(rpc_url: String) -> Result<OnlineClient<PolkadotConfig>, subxt::Error> {
+    let api = OnlineClient::<PolkadotConfig>::from_url(rpc_url).await?;
+    if let Some(metadata) = METADATA.get() {
+        api.set_metadata(metadata.clone());
+    }
+    Ok(api)
+}
+
+/// Whether the node can't serve the state of the block, i.e. has pruned it.
+fn is_state_unavailable(err: &subxt::Error) -> bool {
+    let message = err.to_string();
+    message.contains("State already discarded") || message.contains("pruned")
+}
+
+/// Whether the request failed on the way to the node or timed out, rather than being
+/// answered with an error, e.g. for an unknown block or a pruned state, which a retry
+/// gets again.
+pub fn is_transport_error(err: &subxt::Error) -> bool {
+    use subxt::error::RpcError;
+    match err {
+        subxt::Error::Rpc(RpcError::SubscriptionDropped) => true,
+        subxt::Error::Rpc(RpcError::ClientError(e)) if !is_state_unavailable(err) => {
+            // the client's error is boxed, so it's only told apart by its message
+            let message = e.to_string();
+            message.contains("Networking or low-level protocol error")
+                || message.contains("Request timeout")
+                || message.contains("restart required")
+        }
+        _ => false,
+    }
+}
+
+/// Hashes the data with the relay chain hasher, i.e. blake2_256.
+pub fn hash(data: &[u8]) -> H256 {
+    <PolkadotConfig as Config>::Hasher::hash(data)
+}
+
+/// Fetches the validator account keys of each session at the given block.
+///
+/// With `skip_unavailable`, the sessions whose block state was pruned by the node are
+/// left out instead of failing.
 pub async fn historical_account_keys(
     rpc_url: String,
     input: impl IntoIterator<Item = (SessionIndex, H256)>,
+    skip_unavailable: bool,
 ) -> anyhow::Result<BTreeMap<SessionIndex, Vec<AccountId32>>> {
-    let api = OnlineClient::<PolkadotConfig>::from_url(rpc_url).await?;
+    let api = connect(rpc_url).await?;
 
     let mut map: BTreeMap<SessionIndex, Vec<AccountId32>> = BTreeMap::new();
 
@@ -21,11 +99,344 @@ pub async fn historical_account_keys(
             let storage_query = polkadot::storage()
                 .para_session_info()
                 .account_keys(&session);
-            // TODO: handle errors here
-            let keys = api.storage().at(block_hash).fetch(&storage_query).await?;
-            // TODO: handle None
-            if let Some(keys) = keys {
-                e.insert(keys);
+            let storage_query = address!(storage_query);
+            let keys = match api.storage().at(block_hash).fetch(&storage_query).await {
+                Ok(keys) => keys,
+                Err(err) if is_state_unavailable(&err) && skip_unavailable => {
+                    eprintln!(
+                        "Warning: skipping session {session}, the state at block {block_hash:?} \
+                         is unavailable"
+                    );
+                    crate::report::inc_skipped();
+                    continue;
+                }
+                Err(err) if is_state_unavailable(&err) => {
+                    return Err(err).with_context(|| {
+                        format!(
+                            "the state at block {block_hash:?} is unavailable, the node has \
+                             probably pruned it: use an archive node, or --skip-unavailable \
+                             to proceed without the keys of session {session}"
+                        )
+                    });
+                }
+                Err(err) => {
+                    return Err(err).with_context(|| {
+                        ErrorContext::new(ErrorKind::Rpc).at_block_hash(block_hash)
+                    });
+                }
+            };
+            match keys {
+                Some(keys) => {
+                    e.insert(keys);
+                }
+                None => eprintln!(
+                    "Warning: no account keys of session {session} stored at block {block_hash:?}"
+                ),
+            }
+        }
+    }
+
+    Ok(map)
+}
+
+/// Returns the number of the finalized head.
+pub async fn finalized_head_number(rpc_url: String) -> anyhow::Result<u32> {
+    let api = connect(rpc_url).await?;
+
+    let hash = api.rpc().finalized_head().await?;
+    let header = api
+        .rpc()
+        .header(Some(hash))
+        .await?
+        .context("finalized head header not found")?;
+
+    Ok(header.number)
+}
+
+/// Returns the hash of the finalized head.
+pub async fn finalized_head_hash(rpc_url: String) -> anyhow::Result<H256> {
+    let api = connect(rpc_url).await?;
+    Ok(api.rpc().finalized_head().await?)
+}
+
+/// Resolves the session of each block from `Session.CurrentIndex`.
+///
+/// Sessions are hours long, so the blocks in between two blocks of the same session are
+/// assumed to be in that session too, and only the ranges spanning a session change
+/// are bisected further.
+pub async fn session_indices(
+    rpc_url: String,
+    block_nums: impl IntoIterator<Item = u32>,
+) -> anyhow::Result<BTreeMap<u32, SessionIndex>> {
+    let api = connect(rpc_url).await?;
+
+    let block_nums: Vec<u32> = block_nums
+        .into_iter()
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let mut sessions = BTreeMap::new();
+    if block_nums.is_empty() {
+        return Ok(sessions);
+    }
+
+    let mut ranges = vec![(0, block_nums.len() - 1)];
+    while let Some((lo, hi)) = ranges.pop() {
+        let lo_session = session_at(&api, block_nums[lo], &mut sessions).await?;
+        let hi_session = session_at(&api, block_nums[hi], &mut sessions).await?;
+        if lo_session == hi_session {
+            for block_num in &block_nums[lo..=hi] {
+                sessions.insert(*block_num, lo_session);
+            }
+        } else if hi - lo > 1 {
+            let mid = (lo + hi) / 2;
+            ranges.push((lo, mid));
+            ranges.push((mid, hi));
+        }
+    }
+    Ok(sessions)
+}
+
+async fn session_at(
+    api: &OnlineClient<PolkadotConfig>,
+    block_num: u32,
+    sessions: &mut BTreeMap<u32, SessionIndex>,
+) -> anyhow::Result<SessionIndex> {
+    if let Some(session) = sessions.get(&block_num) {
+        return Ok(*session);
+    }
+    let rpc_error = || ErrorContext::new(ErrorKind::Rpc).at_block(block_num);
+    let hash = api
+        .rpc()
+        .block_hash(Some(block_num.into()))
+        .await
+        .with_context(rpc_error)?
+        .with_context(|| format!("no block hash for block {block_num}"))?;
+    let query = address!(polkadot::storage().session().current_index());
+    let session = api
+        .storage()
+        .at(hash)
+        .fetch_or_default(&query)
+        .await
+        .with_context(rpc_error)?;
+    sessions.insert(block_num, session);
+    Ok(session)
+}
+
+/// Resolves the authors of the blocks from the authority index in their BABE pre-runtime
+/// digest. The BABE authorities are the validators of the session in the same order.
+pub async fn block_authors(
+    rpc_url: String,
+    block_nums: impl IntoIterator<Item = u32>,
+) -> anyhow::Result<BTreeMap<u32, AccountId32>> {
+    let api = connect(rpc_url).await?;
+
+    let mut authors = BTreeMap::new();
+    for block_num in block_nums.into_iter() {
+        let Entry::Vacant(e) = authors.entry(block_num) else {
+            continue;
+        };
+        let rpc_error = || ErrorContext::new(ErrorKind::Rpc).at_block(block_num);
+        let hash = api
+            .rpc()
+            .block_hash(Some(block_num.into()))
+            .await
+            .with_context(rpc_error)?
+            .with_context(|| format!("no block hash for block {block_num}"))?;
+        let header = api
+            .rpc()
+            .header(Some(hash))
+            .await
+            .with_context(rpc_error)?
+            .with_context(|| format!("no header for block {block_num}"))?;
+
+        // all the variants of `PreDigest` start with the `u32` authority index
+        let authority_index = header.digest.logs.iter().find_map(|log| match log {
+            DigestItem::PreRuntime(engine, data) if engine == b"BABE" && data.len() >= 5 => {
+                u32::decode(&mut &data[1..5]).ok()
+            }
+            _ => None,
+        });
+        let Some(authority_index) = authority_index else {
+            eprintln!("no BABE pre-runtime digest in block {block_num}");
+            continue;
+        };
+
+        let validators = api
+            .storage()
+            .at(hash)
+            .fetch(&address!(polkadot::storage().session().validators()))
+            .await
+            .with_context(rpc_error)?
+            .unwrap_or_default();
+        match validators.get(authority_index as usize) {
+            Some(author) => {
+                e.insert(author.clone());
+            }
+            None => eprintln!("no validator with index {authority_index} at block {block_num}"),
+        }
+    }
+
+    Ok(authors)
+}
+
+/// Returns the number of parachain validators at the block, which the erasure coding
+/// of the candidates backed on top of it depends on.
+pub async fn n_validators(rpc_url: String, at: H256) -> anyhow::Result<usize> {
+    let api = connect(rpc_url).await?;
+
+    let query = address!(polkadot::storage().paras_shared().active_validator_keys());
+    let keys = api
+        .storage()
+        .at(at)
+        .fetch(&query)
+        .await
+        .with_context(|| ErrorContext::new(ErrorKind::Rpc).at_block_hash(at))?
+        .unwrap_or_default();
+    Ok(keys.len())
+}
+
+/// Checks whether the storage items we query are compatible with the live metadata.
+///
+/// Every storage item queried by any command must be listed here.
+///
+/// Returns the list of items that changed.
+pub async fn check_metadata(rpc_url: String) -> anyhow::Result<Vec<String>> {
+    let api = connect(rpc_url).await?;
+    let storage = api.storage();
+    let para = polkadot::runtime_types::polkadot_parachain::primitives::Id(0);
+    let code_hash = ValidationCodeHash(H256::zero());
+
+    let checks = [
+        (
+            "ParaSessionInfo.AccountKeys",
+            storage.validate(&polkadot::storage().para_session_info().account_keys(&0)),
+        ),
+        (
+            "Paras.CodeByHash",
+            storage.validate(&polkadot::storage().paras().code_by_hash(&code_hash)),
+        ),
+        (
+            "Paras.CurrentCodeHash",
+            storage.validate(&polkadot::storage().paras().current_code_hash(&para)),
+        ),
+        (
+            "ParasDisputes.Disputes",
+            storage.validate(&polkadot::storage().paras_disputes().disputes_root()),
+        ),
+        (
+            "ParasShared.ActiveValidatorKeys",
+            storage.validate(&polkadot::storage().paras_shared().active_validator_keys()),
+        ),
+        (
+            "Session.CurrentIndex",
+            storage.validate(&polkadot::storage().session().current_index()),
+        ),
+        (
+            "Session.Validators",
+            storage.validate(&polkadot::storage().session().validators()),
+        ),
+    ];
+
+    let changed = checks
+        .into_iter()
+        .filter_map(|(item, result)| result.err().map(|e| format!("{item}: {e}")))
+        .collect();
+    Ok(changed)
+}
+
+/// Reads the disputes from the `ParasDisputes.Disputes` storage at `block_num`,
+/// returning a row per validator that voted on the candidate.
+pub async fn disputes_at(rpc_url: String, block_num: u32) -> anyhow::Result<Vec<DisputeInitiated>> {
+    let api = connect(rpc_url).await?;
+    let rpc_error = |block_num: u32| ErrorContext::new(ErrorKind::Rpc).at_block(block_num);
+
+    let at = api
+        .rpc()
+        .block_hash(Some(block_num.into()))
+        .await
+        .with_context(|| rpc_error(block_num))?
+        .with_context(|| format!("no block hash for block {block_num}"))?;
+
+    let query = address!(polkadot::storage().paras_disputes().disputes_root());
+    let mut iter = api
+        .storage()
+        .at(at)
+        .iter(query, 100)
+        .await
+        .with_context(|| ErrorContext::new(ErrorKind::Rpc).at_block_hash(at))?;
+
+    let mut initiated = Vec::new();
+    let mut block_hashes = BTreeMap::new();
+    while let Some((key, state)) = iter.next().await? {
+        // the key ends with `twox64_concat(session) ++ blake2_128_concat(candidate_hash)`
+        let key = key.0;
+        let candidate_hash = H256::from_slice(&key[key.len() - 32..]);
+        let session_start = key.len() - 32 - 16 - 4;
+        let session_index = SessionIndex::decode(&mut &key[session_start..session_start + 4])?;
+
+        let block_hash = match block_hashes.entry(state.start) {
+            Entry::Occupied(e) => *e.get(),
+            Entry::Vacant(e) => {
+                let hash = api
+                    .rpc()
+                    .block_hash(Some(state.start.into()))
+                    .await
+                    .with_context(|| rpc_error(state.start))?
+                    .with_context(|| format!("no block hash for block {}", state.start))?;
+                *e.insert(hash)
+            }
+        };
+
+        let votes = [
+            (true, &state.validators_for),
+            (false, &state.validators_against),
+        ];
+        for (valid, voters) in votes {
+            for (validator_index, voted) in voters.iter().enumerate() {
+                if voted {
+                    initiated.push(DisputeInitiated {
+                        session_index,
+                        validator_index: validator_index as u32,
+                        block_num: state.start,
+                        block_hash: format!("{block_hash:?}"),
+                        candidate_hash: Some(candidate_hash),
+                        signature: None,
+                        valid,
+                    });
+                }
+            }
+        }
+    }
+    eprintln!(
+        "Found {} invalid votes in the disputes storage at block {block_num}",
+        initiated.iter().filter(|i| !i.valid).count()
+    );
+
+    Ok(initiated)
+}
+
+/// Resolves the canonical block hashes for the given block numbers.
+pub async fn canonical_block_hashes(
+    rpc_url: String,
+    block_nums: impl IntoIterator<Item = u32>,
+) -> anyhow::Result<BTreeMap<u32, H256>> {
+    let api = connect(rpc_url).await?;
+
+    let mut map = BTreeMap::new();
+
+    for block_num in block_nums.into_iter() {
+        if let Entry::Vacant(e) = map.entry(block_num) {
+            let block_hash = api
+                .rpc()
+                .block_hash(Some(block_num.into()))
+                .await
+                .with_context(|| ErrorContext::new(ErrorKind::Rpc).at_block(block_num))?;
+            match block_hash {
+                Some(hash) => {
+                    e.insert(hash);
+                }
+                None => eprintln!("no canonical block hash for block {block_num}"),
             }
         }
     }
@@ -33,15 +444,53 @@ pub async fn historical_account_keys(
     Ok(map)
 }
 
+/// Fetches the validation code of `para_id` that is current at `block_num`,
+/// looking up its hash in `Paras.CurrentCodeHash`.
+pub async fn validation_code_at(
+    pvfs_path: &Path,
+    rpc_url: String,
+    para_id: u32,
+    block_num: u32,
+    no_cache: bool,
+) -> anyhow::Result<(ValidationCodeHash, ValidationCode)> {
+    let api = connect(rpc_url.clone())
+        .await
+        .context(ErrorContext::new(ErrorKind::Rpc))?;
+    let rpc_error = || ErrorContext::new(ErrorKind::Rpc).at_block(block_num);
+    let at = api
+        .rpc()
+        .block_hash(Some(block_num.into()))
+        .await
+        .with_context(rpc_error)?
+        .with_context(|| format!("no block hash for block {block_num}"))?;
+
+    let para = polkadot::runtime_types::polkadot_parachain::primitives::Id(para_id);
+    let query = address!(polkadot::storage().paras().current_code_hash(&para));
+    let code_hash = api
+        .storage()
+        .at(at)
+        .fetch(&query)
+        .await
+        .with_context(rpc_error)?
+        .with_context(|| {
+            format!("para_id {para_id} has no validation code at block {block_num}")
+        })?;
+
+    let code = validation_code_by_hash(pvfs_path, rpc_url, code_hash.clone(), at, no_cache).await?;
+    Ok((code_hash, code))
+}
+
 pub async fn validation_code_by_hash(
     pvfs_path: &Path,
     rpc_url: String,
     code_hash: ValidationCodeHash,
     relay_parent: H256,
+    no_cache: bool,
 ) -> anyhow::Result<ValidationCode> {
     let validation_code_hash = format!("{:?}", code_hash.0);
     let file = pvfs_path.join(&validation_code_hash);
-    if file.exists() {
+    let _guard = crate::cache::lock(&file).await;
+    if !no_cache && crate::cache::is_cached(&file) {
         let bytes = std::fs::read(file)?;
         let pvf = parity_scale_codec::decode_from_bytes(bytes.into())?;
 
@@ -51,15 +500,35 @@ pub async fn validation_code_by_hash(
 
     println!("Fetching Pvf {validation_code_hash}");
 
-    let api = OnlineClient::<PolkadotConfig>::from_url(rpc_url).await?;
+    let api = connect(rpc_url)
+        .await
+        .context(ErrorContext::new(ErrorKind::Rpc))?;
+
+    let storage_query = address!(polkadot::storage().paras().code_by_hash(&code_hash));
 
-    let storage_query = polkadot::storage().paras().code_by_hash(&code_hash);
+    let code = api
+        .storage()
+        .at(relay_parent)
+        .fetch(&storage_query)
+        .await
+        .with_context(|| ErrorContext::new(ErrorKind::Rpc).at_block_hash(relay_parent))?;
 
-    let code = api.storage().at(relay_parent).fetch(&storage_query).await?;
+    // e.g. `diff-pvf` of a code that is no longer stored at the finalized head
+    let code = code.with_context(|| {
+        format!("no validation code {validation_code_hash} at block {relay_parent:?}")
+    })?;
+    crate::report::add_downloaded(code.0.len())?;
+
+    // don't cache the code if the node gave us something else
+    let actual_hash = hash(&code.0);
+    if actual_hash != code_hash.0 {
+        anyhow::bail!("fetched Pvf hashes to {actual_hash:?}, expected {validation_code_hash}");
+    }
 
     // cache the Pvf
-    let code = code.expect("relay_parent and code_hash are valid; qed");
-    std::fs::write(file, code.encode())?;
+    if !no_cache {
+        crate::cache::write_atomic(&file, &code.encode())?;
+    }
 
     Ok(code)
 }